@@ -0,0 +1,48 @@
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use poussiere::Grid;
+
+/// Builds a grid whose upper half is a loose mix of sand and water about to collapse.
+fn falling_grid() -> Grid {
+    let mut grid = Grid::with_seed((512, 512), 1);
+    let materials = grid.materials().clone();
+    let sand = materials.find("Sand").unwrap();
+    let water = materials.find("Water").unwrap();
+
+    for y in 0..256 {
+        for x in 0..512 {
+            if (x + y) % 3 != 0 {
+                let tile_type = if (x / 32 + y / 32) % 2 == 0 {
+                    sand
+                } else {
+                    water
+                };
+                grid.set_tile((x, y).into(), materials.create_tile(tile_type, 0));
+            }
+        }
+    }
+
+    grid
+}
+
+fn step(c: &mut Criterion) {
+    let grid = falling_grid();
+
+    let mut group = c.benchmark_group("step");
+
+    group.bench_function("sequential", |b| {
+        b.iter_batched_ref(
+            || grid.clone(),
+            Grid::step_sequential,
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("parallel", |b| {
+        b.iter_batched_ref(|| grid.clone(), Grid::step, BatchSize::LargeInput)
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, step);
+criterion_main!(benches);
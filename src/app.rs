@@ -1,52 +1,274 @@
 use crate::{
+    camera::Camera,
     gui::Gui,
+    history::History,
     input::InputState,
-    world::{get_color, Grid, Pos2i, Tile, TileType},
+    keybindings::{Action, Keybindings},
+    tools::{self, BrushShape, Tool},
 };
+use imgui::ImString;
 use pixels::{Pixels, SurfaceTexture};
-use rand::{prelude::ThreadRng, thread_rng, Rng};
-use std::error;
-use winit::event::{ElementState, Event};
+use poussiere::{
+    Grid, ImportMode, LoadError, MaterialRegistry, Particle, Pos2i, Rect, ResizeAnchor,
+    StreamingWorld, Tile, TileType, AMBIENT_TEMPERATURE, CHUNK_SIZE, DEFAULT_SEED, TILE_AIR,
+};
+use rand::Rng;
+use std::{
+    env, error,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
+use winit::event::{
+    ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode,
+};
 use winit::{event::WindowEvent, window::Window};
 
+/// The size of a tile on screen when the sandbox starts, in pixels.
 const TILE_SIZE: u64 = 4;
+/// The size of the window when the sandbox starts, in pixels.
 pub const WINDOW_WIDTH: u64 = 1024;
 pub const WINDOW_HEIGHT: u64 = 768;
+/// The largest width and height of the simulated area the world dialog accepts, in tiles.
+const MAX_WORLD_SIZE: u64 = 4096;
+/// The largest tile size the world dialog accepts, in pixels.
+const MAX_TILE_SIZE: u64 = 32;
+
+/// How much the heat and cool brushes change the temperature of a tile each frame.
+const HEAT_BRUSH_STEP: f32 = 25.0;
+/// The temperature range the heat and cool brushes are clamped to.
+const BRUSH_TEMPERATURE_RANGE: (f32, f32) = (-100.0, 2000.0);
+/// The blast power of the bombs dropped with the bomb brush, whose radius is twice the brush size.
+const BOMB_POWER: f32 = 6.0;
+/// How many particles the spray brush emits each frame.
+const SPRAY_RATE: usize = 4;
+/// The speed of the particles emitted by the spray brush per unit of brush size, at most, in cells per step.
+const SPRAY_SPEED: f32 = 0.5;
+
+/// The color drawn where there is no world to show.
+const BACKGROUND_COLOR: [u8; 4] = [16, 16, 16, 255];
+/// How many pixels a touchpad has to scroll to zoom as much as one step of a scroll wheel.
+const PIXELS_PER_SCROLL_STEP: f64 = 50.0;
+
+/// The color previews of the eraser and of brushes which don't place a material are tinted with.
+const PREVIEW_COLOR: [u8; 4] = [255, 255, 255, 255];
+/// How much previews are tinted with the color of what is about to be drawn, between 0 and 1.
+const PREVIEW_OPACITY: f32 = 0.5;
+
+/// The colors burning tiles are drawn with, picked according to their variant.
+const BURNING_COLORS: [[u8; 4]; 3] = [[255, 72, 0, 255], [255, 140, 0, 255], [200, 32, 0, 255]];
+
+/// The materials file loaded at startup, the builtin materials being used when it doesn't exist.
+const MATERIALS_PATH: &str = "assets/materials.ron";
+/// The bindings file loaded at startup, the builtin keyboard shortcuts being used when it doesn't exist.
+const KEYBINDINGS_PATH: &str = "assets/keybindings.ron";
 
 /// A struct storing current user state
 #[derive(Default)]
 pub struct UserState {
     pub current_tile: TileType,
+    pub brush: Brush,
+    pub brush_mode: BrushMode,
+    /// The material replaced by the brush in [`BrushMode::ReplaceMaterial`] mode.
+    pub replace_target: TileType,
+    pub brush_shape: BrushShape,
+    /// The probability for the material brush to place a tile on each cell it covers, between 0 and 1,
+    /// whatever its mode.
+    pub brush_density: f32,
+    pub tool: Tool,
     pub brush_size: u64,
     pub running: bool,
+    /// Whether a single step was requested while the simulation is paused.
+    pub step_requested: bool,
     pub edit_action_flag: Option<EditAction>,
-    pub action_stack: Vec<Vec<Tile>>,
+    pub history: History,
+    pub world_path: ImString,
+    pub image_path: ImString,
+    pub import_mode: ImportMode,
+    pub export_scaled: bool,
+    pub heat_view: bool,
+    /// Whether the new world dialog is shown.
+    pub world_dialog: bool,
+    /// The width and height of the simulated area and the tile size entered in the new world dialog.
+    pub world_width: i32,
+    pub world_height: i32,
+    pub tile_size: i32,
+    pub resize_anchor: ResizeAnchor,
+}
+
+impl UserState {
+    /// Applies the current brush to the tile at the given position.
+    fn apply_brush(&mut self, world: &mut StreamingWorld, pos: Pos2i) {
+        match self.brush {
+            Brush::Material => self.place_tile(world, pos),
+            Brush::Heat => self.heat_tile(world, pos, HEAT_BRUSH_STEP),
+            Brush::Cool => self.heat_tile(world, pos, -HEAT_BRUSH_STEP),
+            // bombs go off once per click and sprays emit particles rather than painting tiles
+            Brush::Bomb | Brush::Spray => {}
+        }
+    }
+
+    /// Applies the current brush to the region of connected tiles of the material at the given position.
+    /// The region being picked by material already, the material brush paints all of it whatever its mode.
+    fn flood_fill(&mut self, world: &mut StreamingWorld, pos: Pos2i) {
+        for cell in flood_region(world, pos) {
+            match self.brush {
+                Brush::Material => self.paint_tile(world, cell),
+                _ => self.apply_brush(world, cell),
+            }
+        }
+    }
+
+    fn heat_tile(&mut self, world: &mut StreamingWorld, pos: Pos2i, delta: f32) {
+        if let Ok(mut tile) = world.get_tile(pos) {
+            tile.temperature = (tile.temperature + delta)
+                .clamp(BRUSH_TEMPERATURE_RANGE.0, BRUSH_TEMPERATURE_RANGE.1);
+            self.edit_tile(world, pos, tile);
+        }
+    }
+
+    /// Paints the current material at the given position if the brush mode allows replacing the tile there.
+    fn place_tile(&mut self, world: &mut StreamingWorld, pos: Pos2i) {
+        let clicked_tile = match world.get_tile(pos) {
+            Ok(clicked_tile) => clicked_tile.tile_type,
+            Err(err) => {
+                eprintln!("Failed to read tile: {}", err);
+                return;
+            }
+        };
+
+        let replaceable = match self.brush_mode {
+            BrushMode::FillAir => {
+                clicked_tile == TileType::AIR || self.current_tile == TileType::AIR
+            }
+            BrushMode::ReplaceAll => true,
+            BrushMode::ReplaceMaterial => clicked_tile == self.replace_target,
+        };
+
+        if replaceable {
+            self.paint_tile(world, pos);
+        }
+    }
+
+    /// Paints the current material at the given position with the probability of the brush density.
+    fn paint_tile(&mut self, world: &mut StreamingWorld, pos: Pos2i) {
+        let grid = world.grid_mut();
+
+        let density = self.brush_density.clamp(0.0, 1.0) as f64;
+        if density < 1.0 && !grid.rng().gen_bool(density) {
+            return;
+        }
+
+        let variant = grid.rng().gen_range(0..=8);
+        let tile = grid.materials().create_tile(self.current_tile, variant);
+        self.edit_tile(world, pos, tile);
+    }
+
+    /// Sets a tile as part of the stroke being drawn so that it can be undone.
+    fn edit_tile(&mut self, world: &mut StreamingWorld, pos: Pos2i, tile: Tile) {
+        let result = world.get_tile(pos).and_then(|before| {
+            world.set_tile(pos, tile)?;
+            self.history.record(pos, before, tile);
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            eprintln!("Failed to edit tile: {}", err);
+        }
+    }
+}
+
+/// What the brush does to the tiles it covers.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Brush {
+    #[default]
+    Material,
+    Heat,
+    Cool,
+    Bomb,
+    Spray,
+}
+
+/// Which tiles the material brush overwrites.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum BrushMode {
+    /// Only air is filled, painting air still erasing everything.
+    #[default]
+    FillAir,
+    /// Every tile is replaced.
+    ReplaceAll,
+    /// Only tiles of the material picked as the replace target are replaced.
+    ReplaceMaterial,
 }
 
 pub enum EditAction {
     Undo,
+    Redo,
     Clear,
+    Save,
+    Load,
+    ImportImage,
+    ExportImage,
+    /// Replaces the world by an empty one simulated over the area entered in the new world dialog.
+    NewWorld,
+    /// Simulates the world over the area entered in the new world dialog, keeping its content.
+    ResizeWorld,
 }
 
 pub struct AppState {
     pixels: Pixels<Window>,
-    world: Grid,
+    /// The world, simulated around the center of the camera and streamed to a temporary directory.
+    world: StreamingWorld,
     input_state: InputState,
     gui: Gui,
     user_state: UserState,
-    rng: ThreadRng,
+    camera: Camera,
+    /// The size of a tile in pixels at the default zoom level and when exporting images at tile size.
+    tile_size: u64,
+    /// The size the window was resized to since the last frame, if it was.
+    pending_resize: Option<(u32, u32)>,
+    /// Where the shape being dragged with the left button started, if one is.
+    drag_start: Option<Pos2i>,
+    /// The cells particles and previews were drawn over during the last frame.
+    overlay_cells: Vec<Pos2i>,
+    /// The region previewed by the flood fill tool along with the cell under the cursor and the origin of the
+    /// loaded area it was found from, so it is only searched again once the cursor moves to another cell.
+    flood_preview: Option<(Pos2i, Pos2i, Vec<Pos2i>)>,
+    /// Whether the last frame was drawn in heat view.
+    drawn_heat_view: bool,
+    /// The camera the last frame was drawn with, `None` before the first one.
+    drawn_camera: Option<Camera>,
+    /// The origin of the loaded area of the world when the last frame was drawn.
+    drawn_origin: Option<Pos2i>,
+    keybindings: Keybindings,
 }
 
 impl AppState {
     pub fn create(window: &Window) -> Result<AppState, Box<dyn error::Error>> {
         let win_size = window.inner_size();
-        let surface = SurfaceTexture::new(win_size.width, win_size.height, window);
-        let pixels = Pixels::new(win_size.width, win_size.height, surface)?;
+        let pixels = create_pixels(window, (win_size.width, win_size.height))?;
+
+        let materials = if Path::new(MATERIALS_PATH).exists() {
+            Arc::new(MaterialRegistry::load_file(MATERIALS_PATH)?)
+        } else {
+            MaterialRegistry::builtin()
+        };
+
+        let keybindings = if Path::new(KEYBINDINGS_PATH).exists() {
+            Keybindings::load_file(KEYBINDINGS_PATH)?
+        } else {
+            Keybindings::builtin()
+        };
 
-        let world = Grid::new((
+        let current_tile = materials.find("Sand").unwrap_or_default();
+
+        let size = (
             (win_size.width as u64 / TILE_SIZE),
             (win_size.height as u64 / TILE_SIZE),
-        ));
+        );
+        let world = open_world(size, DEFAULT_SEED, materials)?;
 
         let gui = Gui::new(window, &pixels);
 
@@ -55,116 +277,793 @@ impl AppState {
             world,
             input_state: Default::default(),
             gui,
+            camera: Camera::new(TILE_SIZE as f32, (win_size.width, win_size.height)),
+            tile_size: TILE_SIZE,
+            pending_resize: None,
+            drag_start: None,
+            overlay_cells: Vec::new(),
+            flood_preview: None,
+            drawn_heat_view: false,
+            drawn_camera: None,
+            drawn_origin: None,
+            keybindings,
             user_state: UserState {
+                current_tile,
                 running: true,
                 brush_size: 4u64,
+                brush_density: 1.0,
+                world_path: ImString::new("world.bin"),
+                image_path: ImString::new("world.png"),
+                world_width: size.0 as i32,
+                world_height: size.1 as i32,
+                tile_size: TILE_SIZE as i32,
                 ..Default::default()
             },
-            rng: thread_rng(),
         })
     }
 
     pub fn draw(&mut self, window: &Window) {
+        if let Some(size) = self.pending_resize.take() {
+            if let Err(err) = self.resize_frame(window, size) {
+                eprintln!("Failed to resize the window: {}", err);
+            }
+        }
+
+        let preview = self.preview_cells();
+        let preview_tint = self.preview_tint();
+
         let frame = self.pixels.get_frame();
+        let heat_view = self.user_state.heat_view;
+        let camera = &self.camera;
+
+        let origin = self.world.origin();
+        let changed_chunks = self.world.grid_mut().take_changed_chunks();
 
-        for (position, tile) in self.world.iter_tiles() {
-            let color = get_color(tile.tile_type, tile.variant);
-            for tx in 0..TILE_SIZE {
-                for ty in 0..TILE_SIZE {
-                    let idx = ((TILE_SIZE * position.y as u64 + ty) * WINDOW_WIDTH * 4
-                        + (TILE_SIZE * position.x as u64 + tx) * 4)
-                        as usize;
-                    frame[idx..(4 + idx)].clone_from_slice(&color[..4])
+        let world = self.world.grid();
+        let materials = world.materials();
+
+        let cell_color = |position: Pos2i| match world.get_tile(position - origin) {
+            Some(tile) => tile_color(materials, &tile, heat_view),
+            None => BACKGROUND_COLOR,
+        };
+
+        // temperatures change without flagging chunks so the heat view is redrawn entirely,
+        // as is everything once the camera or the loaded area moved
+        if heat_view
+            || heat_view != self.drawn_heat_view
+            || self.drawn_camera != Some(*camera)
+            || self.drawn_origin != Some(origin)
+        {
+            for pixel in frame.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&BACKGROUND_COLOR);
+            }
+
+            for position in camera.visible_tiles().positions() {
+                if let Some(tile) = world.get_tile(position - origin) {
+                    let color = tile_color(materials, &tile, heat_view);
+                    draw_cell(frame, camera, position, &color);
                 }
             }
+        } else {
+            let cells = changed_chunks
+                .into_iter()
+                .flat_map(Rect::positions)
+                .map(|position| position + origin)
+                .chain(self.overlay_cells.drain(..));
+
+            for position in cells {
+                draw_cell(frame, camera, position, &cell_color(position));
+            }
         }
 
+        self.overlay_cells.clear();
+
+        for particle in world.particles() {
+            let color = tile_color(materials, &particle.tile, heat_view);
+            let position = particle.cell() + origin;
+            draw_cell(frame, camera, position, &color);
+            self.overlay_cells.push(position);
+        }
+
+        for position in preview {
+            let color = blend(&cell_color(position), &preview_tint, PREVIEW_OPACITY);
+            draw_cell(frame, camera, position, &color);
+            self.overlay_cells.push(position);
+        }
+
+        self.drawn_heat_view = heat_view;
+        self.drawn_camera = Some(*camera);
+        self.drawn_origin = Some(origin);
+
         self.gui.prepare(window).expect("Failed to gui.prepare()");
 
         let gui = &mut self.gui;
         let state = &mut self.user_state;
+        let keybindings = &self.keybindings;
 
         let _ = self.pixels.render_with(|encoder, render_target, context| {
             context.scaling_renderer.render(encoder, render_target);
-            gui.render(encoder, render_target, context, state)
-                .expect("gui.render() failed");
+            gui.render(
+                encoder,
+                render_target,
+                context,
+                state,
+                materials,
+                keybindings,
+            )
+            .expect("gui.render() failed");
         });
     }
 
     pub fn handle_event(&mut self, evt: &Event<()>, window: &Window) {
         if let Event::WindowEvent { event, .. } = evt {
             match event {
-                WindowEvent::MouseInput { state, .. } => {
+                WindowEvent::MouseInput { state, button, .. } => {
                     //whether the input was handled by gui and needs to be handled by the input state
                     let handle_input = !self.gui.handle_event(window, evt);
 
-                    self.input_state.update_input(&event, handle_input);
+                    self.input_state.update_input(event, handle_input);
+
+                    match (button, state) {
+                        (MouseButton::Left, ElementState::Pressed) if handle_input => {
+                            self.begin_edit()
+                        }
+                        (MouseButton::Left, ElementState::Released) => self.finish_edit(),
+                        // the right button erases, joining the stroke of the left button if it is drawing one
+                        (MouseButton::Right, ElementState::Pressed) if handle_input => {
+                            self.user_state.history.begin_stroke(MouseButton::Right)
+                        }
+                        (MouseButton::Right, ElementState::Released) => {
+                            self.user_state.history.end_stroke(MouseButton::Right)
+                        }
+                        _ => {}
+                    }
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } => {
+                    self.gui.handle_event(window, evt);
+
+                    if !self.gui.wants_keyboard() {
+                        self.handle_key(*key);
+                    }
+                }
+                WindowEvent::CursorMoved { .. } => {
+                    let previous = self.input_state.get_mouse_pos();
+
+                    self.input_state
+                        .update_input(event, !self.gui.handle_event(window, evt));
+
+                    if self.input_state.is_button_pressed(MouseButton::Middle) {
+                        let pos = self.input_state.get_mouse_pos();
+                        self.camera.pan((
+                            pos.0 as f64 - previous.0 as f64,
+                            pos.1 as f64 - previous.1 as f64,
+                        ));
+                    }
+                }
+                WindowEvent::Resized(size) => {
+                    self.gui.handle_event(window, evt);
+
+                    // a minimized window has no pixels to draw to
+                    if size.width > 0 && size.height > 0 {
+                        self.pending_resize = Some((size.width, size.height));
+                    }
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let handle_input = !self.gui.handle_event(window, evt);
+
+                    if handle_input {
+                        let steps = match delta {
+                            MouseScrollDelta::LineDelta(_, lines) => *lines as f64,
+                            MouseScrollDelta::PixelDelta(pixels) => {
+                                pixels.y / PIXELS_PER_SCROLL_STEP
+                            }
+                        };
 
-                    if handle_input && *state == ElementState::Pressed {
-                        self.user_state.action_stack.push(self.world.snapshot());
+                        let pos = self.input_state.get_mouse_pos();
+                        self.camera
+                            .zoom_at((pos.0 as f64, pos.1 as f64), steps as f32);
                     }
                 }
-                WindowEvent::CursorMoved { .. } => self
-                    .input_state
-                    .update_input(&event, !self.gui.handle_event(window, evt)),
-                _ => {}
+                WindowEvent::ModifiersChanged(_) => {
+                    self.gui.handle_event(window, evt);
+                    self.input_state.update_input(event, true);
+                }
+                _ => {
+                    self.gui.handle_event(window, evt);
+                }
             }
         }
     }
 
-    pub fn update(&mut self) {
-        if self
-            .input_state
-            .is_button_pressed(winit::event::MouseButton::Left)
-        {
-            let pos = self.input_state.get_mouse_pos();
-            let world_pos = (pos.0 / TILE_SIZE, pos.1 / TILE_SIZE);
+    fn handle_key(&mut self, key: VirtualKeyCode) {
+        let modifiers = self.input_state.modifiers();
+
+        if let Some(action) = self.keybindings.action_for(key, modifiers) {
+            self.perform(action);
+        }
+    }
+
+    /// Performs the action bound to a keyboard shortcut.
+    fn perform(&mut self, action: Action) {
+        let user_state = &mut self.user_state;
+
+        user_state.edit_action_flag = match action {
+            Action::TogglePause => {
+                user_state.running = !user_state.running;
+                return;
+            }
+            Action::Step => {
+                user_state.step_requested = true;
+                return;
+            }
+            Action::SelectMaterial(index) => {
+                let materials = self.world.grid().materials();
 
-            let half_brush_size = self.user_state.brush_size as i64;
+                if let Some((tile_type, _)) = materials.iter().nth(index as usize) {
+                    user_state.current_tile = tile_type;
+                }
+                return;
+            }
+            Action::ShrinkBrush => {
+                user_state.brush_size = (user_state.brush_size - 1).max(1);
+                return;
+            }
+            Action::GrowBrush => {
+                user_state.brush_size += 1;
+                return;
+            }
+            Action::Undo => Some(EditAction::Undo),
+            Action::Redo => Some(EditAction::Redo),
+            Action::Clear => Some(EditAction::Clear),
+            Action::Save => Some(EditAction::Save),
+            Action::Load => Some(EditAction::Load),
+        };
+    }
+
+    /// Starts editing the world with the current tool once the left button is pressed.
+    fn begin_edit(&mut self) {
+        let pos = self.mouse_world_pos();
+
+        // bombs and sprays act around the cursor whatever the tool
+        match self.user_state.brush {
+            Brush::Bomb => {
+                let snapshot = self.world.grid().snapshot();
+                self.drop_bomb();
+                self.user_state.history.push_snapshot_diff(
+                    &snapshot,
+                    self.world.grid(),
+                    self.world.origin(),
+                );
+                return;
+            }
+            Brush::Spray => return,
+            _ => {}
+        }
+
+        match self.user_state.tool {
+            tool if tool.is_shape() => self.drag_start = Some(pos),
+            Tool::FloodFill => {
+                self.user_state.history.begin_stroke(MouseButton::Left);
+                self.flood_fill(pos);
+                self.user_state.history.end_stroke(MouseButton::Left);
+            }
+            _ => self.user_state.history.begin_stroke(MouseButton::Left),
+        }
+    }
+
+    /// Applies the shape being dragged, if any, once the left button is released.
+    fn finish_edit(&mut self) {
+        if let Some(start) = self.drag_start.take() {
+            let cells = self.user_state.tool.shape_cells(
+                start,
+                self.mouse_world_pos(),
+                self.user_state.brush_size,
+                self.user_state.brush_shape,
+            );
+
+            self.user_state.history.begin_stroke(MouseButton::Left);
+            for cell in cells {
+                self.user_state.apply_brush(&mut self.world, cell);
+            }
+        }
+
+        self.user_state.history.end_stroke(MouseButton::Left);
+    }
 
-            for dx in -half_brush_size..half_brush_size + 1 {
-                for dy in -half_brush_size..half_brush_size + 1 {
-                    if dx * dx + dy * dy > (half_brush_size * half_brush_size) - 1 {
-                        continue;
-                    };
-                    let px = (world_pos.0 as i64 + dx) as i64;
-                    let py = (world_pos.1 as i64 + dy) as i64;
+    /// Returns the cells the current tool is about to change, to be highlighted.
+    fn preview_cells(&mut self) -> Vec<Pos2i> {
+        let pos = self.mouse_world_pos();
+        let brush_size = self.user_state.brush_size;
+        let brush_shape = self.user_state.brush_shape;
 
-                    self.place_tile((px, py).into(), self.user_state.current_tile);
+        if self.input_state.is_button_pressed(MouseButton::Right) {
+            return tools::brush_cells(pos, brush_size, brush_shape);
+        }
+
+        match (self.user_state.tool, self.user_state.brush) {
+            (_, Brush::Bomb) => tools::brush_cells(pos, brush_size * 2, BrushShape::Circle),
+            (_, Brush::Spray) => vec![pos],
+            (tool, _) if tool.is_shape() => {
+                let start = self.drag_start.unwrap_or(pos);
+                tool.shape_cells(start, pos, brush_size, brush_shape)
+            }
+            (Tool::FloodFill, _) => {
+                let origin = self.world.origin();
+
+                match &self.flood_preview {
+                    Some((cell, from, region)) if *cell == pos && *from == origin => region.clone(),
+                    _ => {
+                        let region = flood_region(&self.world, pos);
+                        self.flood_preview = Some((pos, origin, region.clone()));
+                        region
+                    }
                 }
             }
+            _ => tools::brush_cells(pos, brush_size, brush_shape),
         }
+    }
+
+    /// Returns the color previews are tinted with.
+    fn preview_tint(&self) -> [u8; 4] {
+        let erasing = self.input_state.is_button_pressed(MouseButton::Right);
+        let tile_type = self.user_state.current_tile;
+
+        if !erasing && self.user_state.brush == Brush::Material && tile_type != TileType::AIR {
+            *self.world.grid().materials().color(tile_type, 0)
+        } else {
+            PREVIEW_COLOR
+        }
+    }
+
+    pub fn update(&mut self) {
+        if self.input_state.is_button_pressed(MouseButton::Right) {
+            for cell in self.stroke_cells() {
+                self.user_state.edit_tile(&mut self.world, cell, TILE_AIR);
+            }
+        } else if self.input_state.is_button_pressed(MouseButton::Left) {
+            if self.user_state.brush == Brush::Spray {
+                self.spray(self.mouse_world_pos());
+            } else if self.user_state.tool == Tool::Freehand {
+                for cell in self.stroke_cells() {
+                    self.user_state.apply_brush(&mut self.world, cell);
+                }
+            }
+        }
+
+        self.input_state.next_frame(self.mouse_world_pos());
 
         if let Some(edit_action) = &self.user_state.edit_action_flag.take() {
+            self.flood_preview = None;
+
             match *edit_action {
                 EditAction::Undo => {
-                    let last_world = self.user_state.action_stack.pop().unwrap();
-                    self.world.restore(last_world);
+                    if let Some(tiles) = self.user_state.history.undo() {
+                        self.write_tiles(tiles);
+                    }
+                }
+                EditAction::Redo => {
+                    if let Some(tiles) = self.user_state.history.redo() {
+                        self.write_tiles(tiles);
+                    }
+                }
+                EditAction::Clear => {
+                    let snapshot = self.world.grid().snapshot();
+                    self.world.grid_mut().clear();
+                    self.user_state.history.push_snapshot_diff(
+                        &snapshot,
+                        self.world.grid(),
+                        self.world.origin(),
+                    );
+                }
+                EditAction::Save => {
+                    if let Err(err) = self.save_world() {
+                        eprintln!("Failed to save world: {}", err);
+                    }
+                }
+                EditAction::Load => {
+                    if let Err(err) = self.load_world() {
+                        eprintln!("Failed to load world: {}", err);
+                    }
+                }
+                EditAction::ImportImage => {
+                    if let Err(err) = self.import_image() {
+                        eprintln!("Failed to import image: {}", err);
+                    }
+                }
+                EditAction::ExportImage => {
+                    if let Err(err) = self.export_image() {
+                        eprintln!("Failed to export image: {}", err);
+                    }
+                }
+                EditAction::NewWorld => match self.open_world() {
+                    Ok(world) => self.replace_world(world),
+                    Err(err) => eprintln!("Failed to create world: {}", err),
+                },
+                EditAction::ResizeWorld => {
+                    if let Err(err) = self.resize_world() {
+                        eprintln!("Failed to resize world: {}", err);
+                    }
                 }
-                EditAction::Clear => self.world.clear(),
             }
         }
 
-        if self.user_state.running {
+        // the simulated area follows the camera around
+        if let Err(err) = self.world.set_center(self.camera.center()) {
+            eprintln!("Failed to stream world: {}", err);
+        }
+
+        if self.user_state.running || self.user_state.step_requested {
             self.world.step();
         }
+
+        self.user_state.step_requested = false;
     }
 
-    fn place_tile(&mut self, pos: Pos2i, tile: TileType) {
-        let variant = self.rng.gen_range(0..=8);
+    /// Recreates the pixel buffer and the gui renderer drawing to it for the given window size.
+    fn resize_frame(
+        &mut self,
+        window: &Window,
+        size: (u32, u32),
+    ) -> Result<(), Box<dyn error::Error>> {
+        self.pixels = create_pixels(window, size)?;
+        self.gui.rebuild_renderer(&self.pixels);
+        self.camera.viewport = size;
+        Ok(())
+    }
 
-        if let Some(clicked_tile) = self.world.get_tile(pos) {
-            if clicked_tile.tile_type != TileType::Air && tile != TileType::Air {
-                return;
-            }
+    /// Returns the size of the simulated area entered in the new world dialog.
+    fn dialog_world_size(&self) -> (u64, u64) {
+        let clamp = |size: i32| (size.max(1) as u64).min(MAX_WORLD_SIZE);
+        (
+            clamp(self.user_state.world_width),
+            clamp(self.user_state.world_height),
+        )
+    }
+
+    /// Shows the size of the simulated area in the new world dialog.
+    fn sync_dialog_world_size(&mut self) {
+        let area = self.world.simulated_area();
+        self.user_state.world_width = (area.max.x - area.min.x + 1) as i32;
+        self.user_state.world_height = (area.max.y - area.min.y + 1) as i32;
+    }
+
+    /// Opens an empty world simulated over the area entered in the new world dialog,
+    /// discarding the chunks streamed so far.
+    fn open_world(&self) -> Result<StreamingWorld, LoadError> {
+        let grid = self.world.grid();
+        open_world(
+            self.dialog_world_size(),
+            grid.seed(),
+            grid.materials().clone(),
+        )
+    }
+
+    /// Swaps the world for another one, zooming back to the tile size of the new world dialog
+    /// with the top left corner of the world in view.
+    fn replace_world(&mut self, world: StreamingWorld) {
+        self.world = world;
+        self.user_state.history.clear();
+        self.overlay_cells.clear();
+        self.sync_dialog_world_size();
+
+        self.tile_size = (self.user_state.tile_size.max(1) as u64).min(MAX_TILE_SIZE);
+        self.user_state.tile_size = self.tile_size as i32;
+        self.camera = Camera::new(self.tile_size as f32, self.camera.viewport);
+    }
+
+    /// Simulates the world over the area entered in the new world dialog, keeping the corner or the center
+    /// of the simulated area chosen in the dialog in place, and zooms to its tile size.
+    fn resize_world(&mut self) -> Result<(), LoadError> {
+        self.world.set_area(
+            area_chunks(self.dialog_world_size()),
+            self.user_state.resize_anchor,
+        )?;
+        self.sync_dialog_world_size();
+
+        self.tile_size = (self.user_state.tile_size.max(1) as u64).min(MAX_TILE_SIZE);
+        self.user_state.tile_size = self.tile_size as i32;
+        self.camera.zoom = self.tile_size as f32;
+
+        // the simulated area follows the center of the camera, which has to move along with it
+        let area = self.world.simulated_area();
+        self.camera.look_at(
+            (
+                (area.min.x + area.max.x + 1) / 2,
+                (area.min.y + area.max.y + 1) / 2,
+            )
+                .into(),
+        );
+        Ok(())
+    }
+
+    /// Removes the chunks streamed to disk, which only live as long as the sandbox.
+    pub fn close(&mut self) {
+        if let Err(err) = remove_directory(&stream_directory()) {
+            eprintln!("Failed to remove streamed chunks: {}", err);
         }
+    }
+
+    /// Returns the cells covered by the brush as it moved along with the cursor since the last frame,
+    /// each one once, so that strokes have no gaps however fast the cursor moves.
+    fn stroke_cells(&self) -> Vec<Pos2i> {
+        let pos = self.mouse_world_pos();
+        let from = self.input_state.get_previous_world_pos().unwrap_or(pos);
 
-        self.world.set_tile(
+        Tool::Line.shape_cells(
+            from,
             pos,
-            Tile {
-                tile_type: tile,
-                variant,
-            },
+            self.user_state.brush_size,
+            self.user_state.brush_shape,
+        )
+    }
+
+    /// Returns the world position of the tile under the cursor.
+    fn mouse_world_pos(&self) -> Pos2i {
+        let pos = self.input_state.get_mouse_pos();
+        self.camera.screen_to_world((pos.0 as f64, pos.1 as f64))
+    }
+
+    fn spray(&mut self, pos: Pos2i) {
+        if self.user_state.current_tile == TileType::AIR {
+            return;
+        }
+
+        let max_speed = self.user_state.brush_size as f32 * SPRAY_SPEED;
+
+        let origin = self.world.origin();
+        let world = self.world.grid_mut();
+
+        for _ in 0..SPRAY_RATE {
+            let rng = world.rng();
+            let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+            let speed = rng.gen_range(0.0..=max_speed);
+            let variant = rng.gen_range(0..=8);
+
+            let tile = world
+                .materials()
+                .create_tile(self.user_state.current_tile, variant);
+            let velocity = (angle.cos() * speed, angle.sin() * speed);
+
+            world.spawn_particle(Particle::new(pos - origin, velocity.into(), tile));
+        }
+    }
+
+    fn drop_bomb(&mut self) {
+        let radius = (self.user_state.brush_size * 2) as f32;
+        let center = self.mouse_world_pos() - self.world.origin();
+        self.world.grid_mut().explode(center, radius, BOMB_POWER);
+    }
+
+    /// Applies the current brush to the region of connected tiles of the material at the given position.
+    fn flood_fill(&mut self, pos: Pos2i) {
+        self.flood_preview = None;
+        self.user_state.flood_fill(&mut self.world, pos);
+    }
+
+    /// Writes tiles undone or redone back into the world.
+    fn write_tiles(&mut self, tiles: Vec<(Pos2i, Tile)>) {
+        for (pos, tile) in tiles {
+            if let Err(err) = self.world.set_tile(pos, tile) {
+                eprintln!("Failed to edit tile: {}", err);
+            }
+        }
+    }
+
+    /// Returns the smallest part of the world containing all of its content,
+    /// or the simulated area if the world is empty.
+    fn world_bounds(&self) -> Result<Rect, LoadError> {
+        Ok(self
+            .world
+            .content_bounds()?
+            .unwrap_or_else(|| self.world.simulated_area()))
+    }
+
+    /// Saves the part of the world with content in it as a world file.
+    fn save_world(&self) -> Result<(), Box<dyn error::Error>> {
+        let grid = self.world.region(self.world_bounds()?)?;
+        let file = File::create(self.user_state.world_path.to_str())?;
+        grid.save(BufWriter::new(file))?;
+        Ok(())
+    }
+
+    /// Replaces the world by the content of a world file, its top left tile going to the origin of the world.
+    fn load_world(&mut self) -> Result<(), Box<dyn error::Error>> {
+        let file = File::open(self.user_state.world_path.to_str())?;
+        let materials = self.world.grid().materials().clone();
+        let grid = Grid::load_with_materials(BufReader::new(file), materials)?;
+
+        self.sync_dialog_world_size();
+        self.user_state.tile_size = self.tile_size as i32;
+
+        let mut world = self.open_world()?;
+        world.paste(&grid, (0, 0).into())?;
+        self.replace_world(world);
+        Ok(())
+    }
+
+    /// Imports an image into the simulated area.
+    fn import_image(&mut self) -> Result<(), Box<dyn error::Error>> {
+        let file = File::open(self.user_state.image_path.to_str())?;
+        let area = self.world.simulated_area();
+        let mut grid = self.world.region(area)?;
+        let snapshot = grid.snapshot();
+
+        grid.import_png(BufReader::new(file), self.user_state.import_mode)?;
+        self.world.paste(&grid, area.min)?;
+        self.user_state
+            .history
+            .push_snapshot_diff(&snapshot, &grid, area.min);
+        Ok(())
+    }
+
+    /// Exports the part of the world with content in it as an image.
+    fn export_image(&self) -> Result<(), Box<dyn error::Error>> {
+        let grid = self.world.region(self.world_bounds()?)?;
+        let file = File::create(self.user_state.image_path.to_str())?;
+        let scale = if self.user_state.export_scaled {
+            self.tile_size as u32
+        } else {
+            1
+        };
+        grid.export_png(BufWriter::new(file), scale)?;
+        Ok(())
+    }
+}
+
+/// Returns the world positions of the loaded tiles connected to the given one by tiles of the same material.
+fn flood_region(world: &StreamingWorld, pos: Pos2i) -> Vec<Pos2i> {
+    let origin = world.origin();
+
+    tools::flood_region(world.grid(), pos - origin)
+        .into_iter()
+        .map(|cell| cell + origin)
+        .collect()
+}
+
+/// Returns the directory the world of the sandbox streams its chunks to.
+fn stream_directory() -> PathBuf {
+    env::temp_dir().join(format!("poussiere-{}", process::id()))
+}
+
+/// Removes a directory and everything in it, if it exists.
+fn remove_directory(directory: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(directory) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the number of chunks covering an area of the given size in tiles.
+fn area_chunks(size: (u64, u64)) -> (u64, u64) {
+    let chunk = CHUNK_SIZE as u64;
+    ((size.0 + chunk - 1) / chunk, (size.1 + chunk - 1) / chunk)
+}
+
+/// Opens an empty world simulated over an area of at least the given size in tiles, streaming its chunks
+/// to a fresh directory.
+fn open_world(
+    size: (u64, u64),
+    seed: u64,
+    materials: Arc<MaterialRegistry>,
+) -> Result<StreamingWorld, LoadError> {
+    let directory = stream_directory();
+    remove_directory(&directory)?;
+    StreamingWorld::open(directory, area_chunks(size), seed, materials)
+}
+
+/// Creates a pixel buffer covering the whole window, one pixel per physical pixel.
+fn create_pixels(window: &Window, size: (u32, u32)) -> Result<Pixels<Window>, pixels::Error> {
+    let surface = SurfaceTexture::new(size.0, size.1, window);
+    Pixels::new(size.0, size.1, surface)
+}
+
+/// Returns the color a tile is drawn with, or the color of its temperature in heat view.
+fn tile_color(materials: &MaterialRegistry, tile: &Tile, heat_view: bool) -> [u8; 4] {
+    if heat_view {
+        heat_color(tile.temperature)
+    } else if tile.burning {
+        BURNING_COLORS[tile.variant as usize % BURNING_COLORS.len()]
+    } else {
+        *materials.color(tile.tile_type, tile.variant)
+    }
+}
+
+/// Mixes a color with another one in the given proportion, between 0 and 1.
+fn blend(color: &[u8; 4], other: &[u8; 4], amount: f32) -> [u8; 4] {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * amount) as u8;
+    [
+        mix(color[0], other[0]),
+        mix(color[1], other[1]),
+        mix(color[2], other[2]),
+        255,
+    ]
+}
+
+/// Fills the pixels the camera shows the given grid cell on, ignoring cells out of view.
+fn draw_cell(frame: &mut [u8], camera: &Camera, position: Pos2i, color: &[u8; 4]) {
+    let (columns, rows) = match camera.tile_pixels(position) {
+        Some(pixels) => pixels,
+        None => return,
+    };
+
+    for y in rows {
+        for x in columns.clone() {
+            let idx = ((y * camera.viewport.0 + x) * 4) as usize;
+            frame[idx..(4 + idx)].clone_from_slice(&color[..4])
+        }
+    }
+}
+
+/// Maps a temperature to a color going from blue when cold to black at ambient temperature,
+/// then red, yellow and white as it heats up.
+fn heat_color(temperature: f32) -> [u8; 4] {
+    let lerp = |from: f32, to: f32, t: f32| (from + (to - from) * t.clamp(0.0, 1.0)) as u8;
+
+    if temperature < AMBIENT_TEMPERATURE {
+        let t = (AMBIENT_TEMPERATURE - temperature) / 100.0;
+        [0, lerp(0.0, 64.0, t), lerp(0.0, 255.0, t), 255]
+    } else if temperature < 500.0 {
+        let t = (temperature - AMBIENT_TEMPERATURE) / (500.0 - AMBIENT_TEMPERATURE);
+        [lerp(0.0, 255.0, t), 0, 0, 255]
+    } else if temperature < 1000.0 {
+        let t = (temperature - 500.0) / 500.0;
+        [255, lerp(0.0, 255.0, t), 0, 255]
+    } else {
+        let t = (temperature - 1000.0) / 500.0;
+        [255, 255, lerp(0.0, 255.0, t), 255]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flood_fill_replaces_regions_of_any_material() {
+        let directory = env::temp_dir().join(format!("poussiere-flood-fill-{}", process::id()));
+        remove_directory(&directory).unwrap();
+
+        let materials = MaterialRegistry::builtin();
+        let stone = materials.create_tile(materials.find("Stone").unwrap(), 0);
+        let sand = materials.find("Sand").unwrap();
+        let mut world = StreamingWorld::open(&directory, (2, 2), 3, materials).unwrap();
+
+        let region = Rect {
+            min: (2, 2).into(),
+            max: (6, 4).into(),
+        };
+        for position in region.positions() {
+            world.set_tile(position, stone).unwrap();
+        }
+
+        let mut user_state = UserState {
+            current_tile: sand,
+            brush_mode: BrushMode::FillAir,
+            brush_density: 1.0,
+            ..Default::default()
+        };
+
+        user_state.history.begin_stroke(MouseButton::Left);
+        user_state.flood_fill(&mut world, (3, 3).into());
+        user_state.history.end_stroke(MouseButton::Left);
+
+        for position in region.positions() {
+            assert_eq!(world.get_tile(position).unwrap().tile_type, sand);
+        }
+        assert_eq!(
+            world.get_tile((1, 3).into()).unwrap().tile_type,
+            TileType::AIR
         );
+        assert_eq!(user_state.history.undo().unwrap().len(), 15);
+
+        remove_directory(&directory).unwrap();
     }
 }
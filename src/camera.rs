@@ -0,0 +1,184 @@
+use poussiere::{Pos2i, Rect};
+use std::ops::Range;
+
+/// The smallest and largest zoom levels, in pixels per tile.
+const ZOOM_RANGE: (f32, f32) = (0.5, 32.0);
+/// How much the zoom level is multiplied by for each step of the scroll wheel.
+const ZOOM_STEP: f32 = 1.25;
+
+/// Maps world positions to the pixels of the window they are drawn to, and back.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Camera {
+    /// The world position shown at the top left corner of the viewport, in tiles.
+    pub offset: (f32, f32),
+    /// The size of a tile on screen, in pixels.
+    pub zoom: f32,
+    /// The size of the area the world is drawn to, in pixels.
+    pub viewport: (u32, u32),
+}
+
+impl Camera {
+    /// Creates a camera showing the world from its top left corner at the given zoom level.
+    pub fn new(zoom: f32, viewport: (u32, u32)) -> Camera {
+        Camera {
+            offset: (0.0, 0.0),
+            zoom: zoom.clamp(ZOOM_RANGE.0, ZOOM_RANGE.1),
+            viewport,
+        }
+    }
+
+    /// Returns the world position of the tile under the given pixel.
+    pub fn screen_to_world(&self, screen: (f64, f64)) -> Pos2i {
+        let (x, y) = self.screen_to_world_f32(screen);
+        (x.floor() as i64, y.floor() as i64).into()
+    }
+
+    fn screen_to_world_f32(&self, screen: (f64, f64)) -> (f32, f32) {
+        (
+            self.offset.0 + screen.0 as f32 / self.zoom,
+            self.offset.1 + screen.1 as f32 / self.zoom,
+        )
+    }
+
+    /// Returns the (horizontal, vertical) ranges of pixels covered by the given tile, clipped to the viewport,
+    /// or `None` if the tile isn't visible.
+    /// Tiles smaller than a pixel cover the pixel they start on.
+    pub fn tile_pixels(&self, position: Pos2i) -> Option<(Range<u32>, Range<u32>)> {
+        let span = |tile: i64, offset: f32, size: u32| {
+            let start = ((tile as f32 - offset) * self.zoom).floor();
+            let end = ((tile as f32 + 1.0 - offset) * self.zoom)
+                .floor()
+                .max(start + 1.0);
+
+            let range = start.max(0.0) as u32..end.min(size as f32).max(0.0) as u32;
+            Some(range).filter(|range| !range.is_empty())
+        };
+
+        Some((
+            span(position.x, self.offset.0, self.viewport.0)?,
+            span(position.y, self.offset.1, self.viewport.1)?,
+        ))
+    }
+
+    /// Returns the world positions of the tiles visible through the viewport.
+    pub fn visible_tiles(&self) -> Rect {
+        Rect {
+            min: self.screen_to_world((0.0, 0.0)),
+            max: self.screen_to_world((self.viewport.0 as f64, self.viewport.1 as f64)),
+        }
+    }
+
+    /// Returns the world position of the tile at the center of the viewport.
+    pub fn center(&self) -> Pos2i {
+        self.screen_to_world((self.viewport.0 as f64 / 2.0, self.viewport.1 as f64 / 2.0))
+    }
+
+    /// Moves the view so that the given world position is at the center of the viewport.
+    pub fn look_at(&mut self, center: Pos2i) {
+        self.offset = (
+            center.x as f32 + 0.5 - self.viewport.0 as f32 / 2.0 / self.zoom,
+            center.y as f32 + 0.5 - self.viewport.1 as f32 / 2.0 / self.zoom,
+        );
+    }
+
+    /// Moves the view along with a cursor dragged by the given number of pixels.
+    pub fn pan(&mut self, delta: (f64, f64)) {
+        self.offset.0 -= delta.0 as f32 / self.zoom;
+        self.offset.1 -= delta.1 as f32 / self.zoom;
+    }
+
+    /// Zooms in by the given number of scroll wheel steps, or out if negative,
+    /// keeping the world position under the given pixel in place.
+    pub fn zoom_at(&mut self, screen: (f64, f64), steps: f32) {
+        let anchor = self.screen_to_world_f32(screen);
+
+        self.zoom = (self.zoom * ZOOM_STEP.powf(steps)).clamp(ZOOM_RANGE.0, ZOOM_RANGE.1);
+        self.offset = (
+            anchor.0 - screen.0 as f32 / self.zoom,
+            anchor.1 - screen.1 as f32 / self.zoom,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zooming_keeps_the_world_under_the_cursor_in_place() {
+        let mut camera = Camera::new(4.0, (800, 600));
+        camera.offset = (-13.5, 7.25);
+        let cursor = (321.0, 123.0);
+
+        for steps in [1.0, 3.0, -2.0, -10.0, 50.0] {
+            let before = camera.screen_to_world_f32(cursor);
+            camera.zoom_at(cursor, steps);
+            let after = camera.screen_to_world_f32(cursor);
+
+            assert!(
+                (before.0 - after.0).abs() < 1e-3,
+                "{:?} {:?}",
+                before,
+                after
+            );
+            assert!(
+                (before.1 - after.1).abs() < 1e-3,
+                "{:?} {:?}",
+                before,
+                after
+            );
+        }
+
+        assert_eq!(camera.zoom, ZOOM_RANGE.1);
+    }
+
+    #[test]
+    fn panning_follows_the_cursor() {
+        let mut camera = Camera::new(4.0, (800, 600));
+        let grabbed = camera.screen_to_world((100.0, 100.0));
+
+        camera.pan((40.0, -20.0));
+
+        assert_eq!(camera.offset, (-10.0, 5.0));
+        assert_eq!(camera.screen_to_world((140.0, 80.0)), grabbed);
+    }
+
+    #[test]
+    fn looked_at_positions_are_centered() {
+        let mut camera = Camera::new(4.0, (800, 600));
+
+        for (zoom, center) in [(4.0, (10, -7)), (0.5, (-1234, 56)), (32.0, (0, 0))] {
+            camera.zoom = zoom;
+            camera.look_at(center.into());
+            assert_eq!(camera.center(), center.into());
+        }
+    }
+
+    #[test]
+    fn visible_tiles_cover_the_viewport() {
+        let mut camera = Camera::new(4.0, (800, 600));
+        assert_eq!(
+            camera.visible_tiles(),
+            Rect {
+                min: (0, 0).into(),
+                max: (200, 150).into()
+            }
+        );
+
+        for corner in [(0.0, 0.0), (799.0, 599.0)] {
+            let tile = camera.screen_to_world(corner);
+            assert!(camera.visible_tiles().contains(tile));
+            assert!(camera.tile_pixels(tile).is_some());
+        }
+
+        camera.offset = (-10.5, 3.25);
+        camera.zoom = 0.5;
+        assert_eq!(
+            camera.visible_tiles(),
+            Rect {
+                min: (-11, 3).into(),
+                max: (1589, 1203).into()
+            }
+        );
+    }
+}
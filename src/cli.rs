@@ -0,0 +1,131 @@
+use poussiere::{Grid, MaterialRegistry};
+use std::{
+    error,
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    sync::Arc,
+    time::Instant,
+};
+
+pub const USAGE: &str = "usage:
+    poussière                      open the interactive sandbox
+    poussière run [options]        simulate a world without opening a window
+
+run options:
+    --world <file>     world file to simulate (required)
+    --steps <n>        number of simulation steps to run (required)
+    --seed <n>         reseed the simulation instead of using the world seed
+    --materials <file> simulate with the materials of the given file instead of the builtin ones
+    --out <file>       where to write the resulting world
+    --stats <file>     write the tile count of every material after each step as CSV";
+
+/// Options of the `run` command.
+#[derive(Default)]
+struct RunOptions {
+    world: Option<String>,
+    steps: Option<u64>,
+    seed: Option<u64>,
+    materials: Option<String>,
+    out: Option<String>,
+    stats: Option<String>,
+}
+
+impl RunOptions {
+    fn parse(args: &[String]) -> Result<RunOptions, Box<dyn error::Error>> {
+        let mut options = RunOptions::default();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("missing value for `{}`", arg))
+            };
+
+            match arg.as_str() {
+                "--world" => options.world = Some(value()?),
+                "--steps" => options.steps = Some(value()?.parse()?),
+                "--seed" => options.seed = Some(value()?.parse()?),
+                "--materials" => options.materials = Some(value()?),
+                "--out" => options.out = Some(value()?),
+                "--stats" => options.stats = Some(value()?),
+                _ => return Err(format!("unknown option `{}`\n\n{}", arg, USAGE).into()),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Runs the `run` command: loads a world, steps it and writes the result without any window.
+pub fn run(args: &[String]) -> Result<(), Box<dyn error::Error>> {
+    let options = RunOptions::parse(args)?;
+
+    let world_path = options.world.ok_or("missing `--world` option")?;
+    let steps = options.steps.ok_or("missing `--steps` option")?;
+
+    let materials = match &options.materials {
+        Some(path) => Arc::new(MaterialRegistry::load_file(path)?),
+        None => MaterialRegistry::builtin(),
+    };
+
+    let mut world = Grid::load_with_materials(BufReader::new(File::open(&world_path)?), materials)?;
+
+    if let Some(seed) = options.seed {
+        world.set_seed(seed);
+    }
+
+    let mut stats = match &options.stats {
+        Some(path) => {
+            let mut writer = BufWriter::new(File::create(path)?);
+            write!(writer, "step")?;
+            for (_, material) in world.materials().iter() {
+                write!(writer, ",{}", material.name)?;
+            }
+            writeln!(writer)?;
+            Some(writer)
+        }
+        None => None,
+    };
+
+    let start = Instant::now();
+
+    for step in 1..=steps {
+        world.step();
+
+        if let Some(writer) = &mut stats {
+            write_stats(writer, step, &world)?;
+        }
+    }
+
+    eprintln!(
+        "simulated {} steps of {} (seed {}) in {:.2?}",
+        steps,
+        world_path,
+        world.seed(),
+        start.elapsed()
+    );
+
+    if let Some(mut writer) = stats {
+        writer.flush()?;
+    }
+
+    if let Some(out) = options.out {
+        world.save(BufWriter::new(File::create(out)?))?;
+    }
+
+    Ok(())
+}
+
+fn write_stats<W: Write>(writer: &mut W, step: u64, world: &Grid) -> std::io::Result<()> {
+    let mut counts = vec![0u64; world.materials().len()];
+    for (_, tile) in world.iter_tiles() {
+        counts[tile.tile_type.0 as usize] += 1;
+    }
+
+    write!(writer, "{}", step)?;
+    for count in counts {
+        write!(writer, ",{}", count)?;
+    }
+    writeln!(writer)
+}
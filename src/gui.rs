@@ -1,9 +1,13 @@
-use crate::{app::UserState, TileType};
+use crate::{
+    app::{Brush, BrushMode, UserState},
+    keybindings::{Action, Keybindings},
+    tools::{BrushShape, Tool},
+};
 use imgui::*;
 use imgui_wgpu::RendererConfig;
 use pixels::{wgpu, PixelsContext};
-use std::time::Instant;
-use strum::IntoEnumIterator;
+use poussiere::{ImportMode, MaterialRegistry, ResizeAnchor, TileType};
+use std::{convert::TryFrom, time::Instant};
 
 pub struct Gui {
     imgui: imgui::Context,
@@ -23,7 +27,7 @@ impl Gui {
         let mut platform = imgui_winit_support::WinitPlatform::init(&mut imgui);
         platform.attach_window(
             imgui.io_mut(),
-            &window,
+            window,
             imgui_winit_support::HiDpiMode::Default,
         );
 
@@ -46,13 +50,7 @@ impl Gui {
             style.colors[color] = gamma_to_linear(style.colors[color]);
         }
 
-        let device = pixels.device();
-        let queue = pixels.queue();
-        let config = RendererConfig {
-            texture_format: wgpu::TextureFormat::Bgra8UnormSrgb,
-            ..Default::default()
-        };
-        let renderer = imgui_wgpu::Renderer::new(&mut imgui, &device, &queue, config);
+        let renderer = create_renderer(&mut imgui, pixels);
 
         Self {
             imgui,
@@ -62,6 +60,12 @@ impl Gui {
         }
     }
 
+    /// Rebuilds the renderer for another pixel buffer, as when the window is resized,
+    /// keeping the state of the gui windows.
+    pub fn rebuild_renderer(&mut self, pixels: &pixels::Pixels<winit::window::Window>) {
+        self.renderer = create_renderer(&mut self.imgui, pixels);
+    }
+
     pub fn prepare(
         &mut self,
         window: &winit::window::Window,
@@ -80,8 +84,11 @@ impl Gui {
         render_target: &wgpu::TextureView,
         context: &PixelsContext,
         user_state: &mut UserState,
+        materials: &MaterialRegistry,
+        keybindings: &Keybindings,
     ) -> imgui_wgpu::RendererResult<()> {
         let ui = self.imgui.frame();
+        let label = |text: &str, action: Action| ImString::new(keybindings.label(text, action));
 
         let win = Window::new(im_str!("poussière"));
         win.build(&ui, || {
@@ -89,9 +96,13 @@ impl Gui {
 
             // material radio buttons
 
-            for tile_type in TileType::iter() {
-                let name: &'static str = tile_type.into();
-                if ui.radio_button_bool(&ImString::new(name), tile_type == user_state.current_tile)
+            for (index, (tile_type, material)) in materials.iter().enumerate() {
+                let label = match u8::try_from(index) {
+                    Ok(index) => keybindings.label(&material.name, Action::SelectMaterial(index)),
+                    Err(_) => material.name.clone(),
+                };
+
+                if ui.radio_button_bool(&ImString::new(label), tile_type == user_state.current_tile)
                 {
                     user_state.current_tile = tile_type;
                 };
@@ -99,48 +110,234 @@ impl Gui {
 
             ui.new_line();
 
+            // brush selector
+
+            ui.text("Brush");
+            ui.radio_button(im_str!("Material"), &mut user_state.brush, Brush::Material);
+            ui.same_line(0.0);
+            ui.radio_button(im_str!("Heat"), &mut user_state.brush, Brush::Heat);
+            ui.same_line(0.0);
+            ui.radio_button(im_str!("Cool"), &mut user_state.brush, Brush::Cool);
+            ui.same_line(0.0);
+            ui.radio_button(im_str!("Bomb"), &mut user_state.brush, Brush::Bomb);
+            ui.same_line(0.0);
+            ui.radio_button(im_str!("Spray"), &mut user_state.brush, Brush::Spray);
+
+            ui.new_line();
+
+            // brush mode selector
+
+            ui.text("Brush mode");
+            ui.radio_button(
+                im_str!("Fill air"),
+                &mut user_state.brush_mode,
+                BrushMode::FillAir,
+            );
+            ui.same_line(0.0);
+            ui.radio_button(
+                im_str!("Replace all"),
+                &mut user_state.brush_mode,
+                BrushMode::ReplaceAll,
+            );
+            ui.same_line(0.0);
+            ui.radio_button(
+                im_str!("Replace"),
+                &mut user_state.brush_mode,
+                BrushMode::ReplaceMaterial,
+            );
+
+            Slider::new(im_str!("Density"))
+                .range(0.01..=1.0)
+                .build(&ui, &mut user_state.brush_density);
+
+            if user_state.brush_mode == BrushMode::ReplaceMaterial {
+                let names: Vec<ImString> = materials
+                    .iter()
+                    .map(|(_, material)| ImString::new(&material.name))
+                    .collect();
+                let items: Vec<&ImString> = names.iter().collect();
+                let mut target = user_state.replace_target.0 as usize;
+
+                if ComboBox::new(im_str!("Replaced material")).build_simple_string(
+                    &ui,
+                    &mut target,
+                    &items,
+                ) {
+                    user_state.replace_target = TileType(target as u8);
+                }
+            }
+
+            ui.radio_button(
+                im_str!("Circle"),
+                &mut user_state.brush_shape,
+                BrushShape::Circle,
+            );
+            ui.same_line(0.0);
+            ui.radio_button(
+                im_str!("Square"),
+                &mut user_state.brush_shape,
+                BrushShape::Square,
+            );
+
+            ui.new_line();
+
+            // tool selector
+
+            ui.text("Tool");
+            ui.radio_button(im_str!("Freehand"), &mut user_state.tool, Tool::Freehand);
+            ui.same_line(0.0);
+            ui.radio_button(im_str!("Line"), &mut user_state.tool, Tool::Line);
+            ui.same_line(0.0);
+            ui.radio_button(im_str!("Fill"), &mut user_state.tool, Tool::FloodFill);
+            ui.radio_button(im_str!("Rectangle"), &mut user_state.tool, Tool::Rectangle);
+            ui.same_line(0.0);
+            ui.radio_button(
+                im_str!("Filled rectangle"),
+                &mut user_state.tool,
+                Tool::FilledRectangle,
+            );
+            ui.same_line(0.0);
+            ui.radio_button(im_str!("Ellipse"), &mut user_state.tool, Tool::Ellipse);
+            ui.text("Right click erases");
+
+            ui.new_line();
+
             // brush size selector
 
             ui.text("Brush size");
-            if ui.small_button(im_str!("-")) && user_state.brush_size > 1 {
+            if ui.small_button(&label("-", Action::ShrinkBrush)) && user_state.brush_size > 1 {
                 user_state.brush_size -= 1;
             }
             ui.same_line_with_spacing(32f32, 0f32);
             ui.text(format!("{}", user_state.brush_size));
             ui.same_line_with_spacing(50f32, 0f32);
-            if ui.small_button(im_str!("+")) {
+            if ui.small_button(&label("+", Action::GrowBrush)) {
                 user_state.brush_size += 1;
             }
             ui.new_line();
 
             ui.text("World");
 
-            let text: &ImStr = if user_state.running {
-                &im_str!("Stop")
-            } else {
-                &im_str!("Start")
-            };
+            let text = if user_state.running { "Stop" } else { "Start" };
 
-            if ui.small_button(text) {
+            if ui.small_button(&label(text, Action::TogglePause)) {
                 user_state.running = !user_state.running;
             }
 
+            if !user_state.running {
+                ui.same_line(0.0);
+
+                if ui.small_button(&label("Step", Action::Step)) {
+                    user_state.step_requested = true;
+                }
+            }
+
+            ui.checkbox(im_str!("Heat view"), &mut user_state.heat_view);
+
+            if ui.small_button(im_str!("New world...")) {
+                user_state.world_dialog = true;
+            }
+
             ui.new_line();
 
             // undo handling
 
-            let disabled_undo = if user_state.action_stack.is_empty() {
-                (ui.push_style_var(StyleVar::Alpha(0.1)), true)
-            } else {
+            let disabled_undo = if user_state.history.can_undo() {
                 (ui.push_style_var(StyleVar::Alpha(1.0)), false)
+            } else {
+                (ui.push_style_var(StyleVar::Alpha(0.1)), true)
             };
 
-            if ui.small_button(&im_str!("Undo")) && !disabled_undo.1 {
+            if ui.small_button(&label("Undo", Action::Undo)) && !disabled_undo.1 {
                 user_state.edit_action_flag = Some(crate::app::EditAction::Undo);
             }
 
             disabled_undo.0.pop(&ui);
 
+            ui.same_line(0.0);
+
+            let disabled_redo = if user_state.history.can_redo() {
+                (ui.push_style_var(StyleVar::Alpha(1.0)), false)
+            } else {
+                (ui.push_style_var(StyleVar::Alpha(0.1)), true)
+            };
+
+            if ui.small_button(&label("Redo", Action::Redo)) && !disabled_redo.1 {
+                user_state.edit_action_flag = Some(crate::app::EditAction::Redo);
+            }
+
+            disabled_redo.0.pop(&ui);
+
+            const MEGABYTE: usize = 1024 * 1024;
+
+            let mut budget = (user_state.history.budget() / MEGABYTE) as i32;
+            if ui
+                .input_int(im_str!("Undo memory (MB)"), &mut budget)
+                .build()
+            {
+                user_state
+                    .history
+                    .set_budget(budget.max(0) as usize * MEGABYTE);
+            }
+
+            ui.text(format!(
+                "{:.1} MB used",
+                user_state.history.used() as f32 / MEGABYTE as f32
+            ));
+
+            // world file handling
+
+            ui.input_text(im_str!("File"), &mut user_state.world_path)
+                .resize_buffer(true)
+                .build();
+
+            if ui.small_button(&label("Save", Action::Save)) {
+                user_state.edit_action_flag = Some(crate::app::EditAction::Save);
+            }
+
+            ui.same_line(0.0);
+
+            if ui.small_button(&label("Load", Action::Load)) {
+                user_state.edit_action_flag = Some(crate::app::EditAction::Load);
+            }
+
+            ui.new_line();
+
+            // image import / export
+
+            ui.input_text(im_str!("Image"), &mut user_state.image_path)
+                .resize_buffer(true)
+                .build();
+
+            if ui.small_button(im_str!("Import PNG")) {
+                user_state.edit_action_flag = Some(crate::app::EditAction::ImportImage);
+            }
+
+            ui.same_line(0.0);
+
+            if ui.small_button(im_str!("Export PNG")) {
+                user_state.edit_action_flag = Some(crate::app::EditAction::ExportImage);
+            }
+
+            ui.radio_button(
+                im_str!("Crop"),
+                &mut user_state.import_mode,
+                ImportMode::Crop,
+            );
+            ui.same_line(0.0);
+            ui.radio_button(
+                im_str!("Scale"),
+                &mut user_state.import_mode,
+                ImportMode::Scale,
+            );
+
+            ui.checkbox(
+                im_str!("Export at tile size"),
+                &mut user_state.export_scaled,
+            );
+
+            ui.new_line();
+
             let red_bg = ui.push_style_colors(&[
                 (StyleColor::Button, [0.8, 0.0, 0.0, 1.0]),
                 (StyleColor::ButtonHovered, [0.8, 0.0, 0.0, 0.5]),
@@ -149,7 +346,7 @@ impl Gui {
 
             ui.new_line();
 
-            if ui.small_button(&im_str!("Clear World")) {
+            if ui.small_button(&label("Clear World", Action::Clear)) {
                 user_state.edit_action_flag = Some(crate::app::EditAction::Clear);
             }
 
@@ -158,6 +355,48 @@ impl Gui {
             ui.new_line();
         });
 
+        if user_state.world_dialog {
+            let mut opened = true;
+
+            Window::new(im_str!("New world"))
+                .opened(&mut opened)
+                .always_auto_resize(true)
+                .build(&ui, || {
+                    ui.text("Simulated area, in tiles");
+                    ui.input_int(im_str!("Width"), &mut user_state.world_width)
+                        .build();
+                    ui.input_int(im_str!("Height"), &mut user_state.world_height)
+                        .build();
+                    ui.input_int(im_str!("Tile size"), &mut user_state.tile_size)
+                        .build();
+
+                    if ui.small_button(im_str!("Create")) {
+                        user_state.edit_action_flag = Some(crate::app::EditAction::NewWorld);
+                    }
+
+                    ui.new_line();
+
+                    ui.text("Resize keeping the simulated area anchored at");
+                    ui.radio_button(
+                        im_str!("Top left"),
+                        &mut user_state.resize_anchor,
+                        ResizeAnchor::TopLeft,
+                    );
+                    ui.same_line(0.0);
+                    ui.radio_button(
+                        im_str!("Center"),
+                        &mut user_state.resize_anchor,
+                        ResizeAnchor::Center,
+                    );
+
+                    if ui.small_button(im_str!("Resize")) {
+                        user_state.edit_action_flag = Some(crate::app::EditAction::ResizeWorld);
+                    }
+                });
+
+            user_state.world_dialog = opened;
+        }
+
         let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
                 attachment: render_target,
@@ -184,6 +423,23 @@ impl Gui {
 
         self.imgui.io().want_capture_mouse
     }
+
+    /// Returns whether the gui uses the keyboard, as when typing in a text field.
+    pub fn wants_keyboard(&self) -> bool {
+        self.imgui.io().want_capture_keyboard
+    }
+}
+
+fn create_renderer(
+    imgui: &mut imgui::Context,
+    pixels: &pixels::Pixels<winit::window::Window>,
+) -> imgui_wgpu::Renderer {
+    let config = RendererConfig {
+        texture_format: wgpu::TextureFormat::Bgra8UnormSrgb,
+        ..Default::default()
+    };
+
+    imgui_wgpu::Renderer::new(imgui, pixels.device(), pixels.queue(), config)
 }
 
 fn gamma_to_linear(color: [f32; 4]) -> [f32; 4] {
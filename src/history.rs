@@ -0,0 +1,347 @@
+use poussiere::{Grid, Pos2i, Tile};
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+};
+use winit::event::MouseButton;
+
+/// The memory edits may use by default, in bytes.
+pub const DEFAULT_BUDGET: usize = 64 * 1024 * 1024;
+
+/// A tile changed by an edit, with its value before and after it.
+#[derive(Clone, Copy)]
+struct TileChange {
+    position: Pos2i,
+    before: Tile,
+    after: Tile,
+}
+
+/// The tiles changed by a single user action, such as a brush stroke.
+struct Edit {
+    changes: Vec<TileChange>,
+}
+
+impl Edit {
+    /// Returns the memory used by this edit, in bytes.
+    fn size(&self) -> usize {
+        mem::size_of::<Edit>() + self.changes.len() * mem::size_of::<TileChange>()
+    }
+}
+
+/// The tiles changed by the stroke being recorded.
+struct Stroke {
+    /// The mouse buttons drawing the stroke, which ends once they are all released.
+    buttons: Vec<MouseButton>,
+    /// Every tile changed by the stroke, in the order it was first changed.
+    changes: Vec<TileChange>,
+    /// The index of the change of each tile in `changes`.
+    indices: HashMap<Pos2i, usize>,
+}
+
+/// The undo and redo stacks of the sandbox.
+///
+/// Edits only store the tiles they changed. Once they use more memory than the budget of the history,
+/// the oldest edits are forgotten.
+pub struct History {
+    undo: VecDeque<Edit>,
+    redo: Vec<Edit>,
+    /// The memory used by the edits of both stacks, in bytes.
+    used: usize,
+    /// The memory the stored edits may use, in bytes.
+    budget: usize,
+    /// The stroke being recorded, if one is.
+    stroke: Option<Stroke>,
+}
+
+impl History {
+    /// Creates an empty history storing edits up to the given number of bytes.
+    pub fn new(budget: usize) -> History {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            used: 0,
+            budget,
+            stroke: None,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Returns the memory used by the stored edits, in bytes.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Changes the memory the stored edits may use, forgetting the oldest ones if they don't fit anymore.
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.trim();
+    }
+
+    /// Starts recording a stroke drawn with the given button, every tile changed until
+    /// [`History::end_stroke`] being part of the same edit.
+    /// Strokes started while another one is being drawn with another button join it.
+    pub fn begin_stroke(&mut self, button: MouseButton) {
+        let stroke = self.stroke.get_or_insert_with(|| Stroke {
+            buttons: Vec::new(),
+            changes: Vec::new(),
+            indices: HashMap::new(),
+        });
+
+        if !stroke.buttons.contains(&button) {
+            stroke.buttons.push(button);
+        }
+    }
+
+    /// Records a tile changed by the stroke being recorded, with its value before and after the change.
+    pub fn record(&mut self, position: Pos2i, before: Tile, after: Tile) {
+        let stroke = match &mut self.stroke {
+            Some(stroke) => stroke,
+            None => return,
+        };
+
+        match stroke.indices.get(&position) {
+            Some(&idx) => stroke.changes[idx].after = after,
+            None => {
+                stroke.indices.insert(position, stroke.changes.len());
+                stroke.changes.push(TileChange {
+                    position,
+                    before,
+                    after,
+                });
+            }
+        }
+    }
+
+    /// Stops drawing the current stroke with the given button, storing it once no button draws it anymore.
+    pub fn end_stroke(&mut self, button: MouseButton) {
+        let stroke = match &mut self.stroke {
+            Some(stroke) => stroke,
+            None => return,
+        };
+
+        stroke.buttons.retain(|pressed| *pressed != button);
+
+        if stroke.buttons.is_empty() {
+            let changes = self.stroke.take().unwrap().changes;
+            self.push(Edit { changes });
+        }
+    }
+
+    /// Stores the changes made to a grid since the given snapshot of its tiles was taken,
+    /// the top left tile of the grid being at the given world position.
+    pub fn push_snapshot_diff(&mut self, snapshot: &[Tile], grid: &Grid, origin: Pos2i) {
+        let changes = grid
+            .iter_tiles()
+            .zip(snapshot)
+            .filter(|((_, after), before)| *after != *before)
+            .map(|((position, after), before)| TileChange {
+                position: origin + position,
+                before: *before,
+                after: *after,
+            })
+            .collect();
+
+        self.push(Edit { changes });
+    }
+
+    /// Reverts the last edit, returning the tiles to write back into the world in order, if there was one.
+    pub fn undo(&mut self) -> Option<Vec<(Pos2i, Tile)>> {
+        let edit = self.undo.pop_back()?;
+        let tiles = edit
+            .changes
+            .iter()
+            .rev()
+            .map(|change| (change.position, change.before))
+            .collect();
+
+        self.redo.push(edit);
+        Some(tiles)
+    }
+
+    /// Applies the last undone edit again, returning the tiles to write into the world in order,
+    /// if there was one.
+    pub fn redo(&mut self) -> Option<Vec<(Pos2i, Tile)>> {
+        let edit = self.redo.pop()?;
+        let tiles = edit
+            .changes
+            .iter()
+            .map(|change| (change.position, change.after))
+            .collect();
+
+        self.undo.push_back(edit);
+        Some(tiles)
+    }
+
+    /// Forgets every edit, as when the world they apply to is replaced.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.used = 0;
+        self.stroke = None;
+    }
+
+    fn push(&mut self, edit: Edit) {
+        if edit.changes.is_empty() {
+            return;
+        }
+
+        for undone in self.redo.drain(..) {
+            self.used -= undone.size();
+        }
+
+        self.used += edit.size();
+        self.undo.push_back(edit);
+        self.trim();
+    }
+
+    /// Forgets the oldest edits, then the undone edits furthest from the current state of the world,
+    /// until the stored edits fit in the budget.
+    fn trim(&mut self) {
+        while self.used > self.budget {
+            let edit = match self.undo.pop_front() {
+                Some(edit) => edit,
+                None if !self.redo.is_empty() => self.redo.remove(0),
+                None => break,
+            };
+
+            self.used -= edit.size();
+        }
+    }
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new(DEFAULT_BUDGET)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poussiere::TILE_AIR;
+
+    fn tile(variant: u8) -> Tile {
+        Tile {
+            variant,
+            ..TILE_AIR
+        }
+    }
+
+    /// Records a stroke changing the given tiles from a variant to another.
+    fn stroke(history: &mut History, changes: &[((i64, i64), u8, u8)]) {
+        history.begin_stroke(MouseButton::Left);
+        for (position, before, after) in changes {
+            history.record((*position).into(), tile(*before), tile(*after));
+        }
+        history.end_stroke(MouseButton::Left);
+    }
+
+    fn variants(tiles: Option<Vec<(Pos2i, Tile)>>) -> Vec<((i64, i64), u8)> {
+        tiles
+            .unwrap()
+            .into_iter()
+            .map(|(position, tile)| ((position.x, position.y), tile.variant))
+            .collect()
+    }
+
+    #[test]
+    fn edits_are_undone_and_redone_in_order() {
+        let mut history = History::default();
+        stroke(&mut history, &[((0, 0), 0, 1), ((1, 0), 0, 1)]);
+        stroke(&mut history, &[((0, 0), 1, 2)]);
+
+        assert_eq!(variants(history.undo()), [((0, 0), 1)]);
+        assert_eq!(variants(history.undo()), [((1, 0), 0), ((0, 0), 0)]);
+        assert!(history.undo().is_none());
+
+        assert_eq!(variants(history.redo()), [((0, 0), 1), ((1, 0), 1)]);
+        assert_eq!(variants(history.redo()), [((0, 0), 2)]);
+        assert!(history.redo().is_none());
+    }
+
+    #[test]
+    fn strokes_keep_the_first_and_last_value_of_each_tile() {
+        let mut history = History::default();
+        stroke(
+            &mut history,
+            &[((3, 3), 0, 1), ((3, 3), 1, 2), ((3, 3), 2, 0)],
+        );
+
+        assert_eq!(variants(history.undo()), [((3, 3), 0)]);
+        assert_eq!(variants(history.redo()), [((3, 3), 0)]);
+    }
+
+    #[test]
+    fn new_edits_clear_the_redo_stack() {
+        let mut history = History::default();
+        stroke(&mut history, &[((0, 0), 0, 1)]);
+        stroke(&mut history, &[((0, 0), 1, 2)]);
+        history.undo();
+        let used = history.used();
+
+        stroke(&mut history, &[((5, 5), 0, 3)]);
+
+        assert!(!history.can_redo());
+        assert!(history.redo().is_none());
+        assert_eq!(history.used(), used);
+    }
+
+    #[test]
+    fn strokes_last_until_every_button_is_released() {
+        let mut history = History::default();
+
+        history.begin_stroke(MouseButton::Left);
+        history.record((0, 0).into(), tile(0), tile(1));
+        history.begin_stroke(MouseButton::Right);
+        history.record((1, 0).into(), tile(0), tile(2));
+        history.end_stroke(MouseButton::Left);
+        assert!(!history.can_undo());
+
+        history.record((2, 0).into(), tile(0), tile(3));
+        history.end_stroke(MouseButton::Right);
+
+        assert_eq!(
+            variants(history.undo()),
+            [((2, 0), 0), ((1, 0), 0), ((0, 0), 0)]
+        );
+        assert!(!history.can_undo());
+    }
+
+    #[test]
+    fn the_oldest_edits_are_forgotten_once_over_budget() {
+        let mut history = History::default();
+        stroke(&mut history, &[((0, 0), 0, 1)]);
+        let size = history.used();
+
+        let mut history = History::new(2 * size);
+        for variant in 1..=3 {
+            stroke(&mut history, &[((0, 0), variant - 1, variant)]);
+        }
+
+        assert_eq!(history.used(), 2 * size);
+        assert_eq!(variants(history.undo()), [((0, 0), 2)]);
+        assert_eq!(variants(history.undo()), [((0, 0), 1)]);
+        assert!(!history.can_undo());
+
+        // undone edits still count until they are forgotten too
+        assert_eq!(history.used(), 2 * size);
+        history.set_budget(size);
+        assert_eq!(history.used(), size);
+        assert_eq!(variants(history.redo()), [((0, 0), 2)]);
+
+        history.set_budget(0);
+        assert_eq!(history.used(), 0);
+        assert!(!history.can_undo() && !history.can_redo());
+    }
+}
@@ -1,9 +1,14 @@
-use winit::event::{ElementState, MouseButton, WindowEvent};
+use poussiere::Pos2i;
+use winit::event::{ElementState, ModifiersState, MouseButton, WindowEvent};
 
 #[derive(Default)]
 pub struct InputState {
     mouse_pos: (u64, u64),
+    /// The world position under the cursor at the end of the last frame, `None` if a mouse button was
+    /// pressed since.
+    previous_world_pos: Option<Pos2i>,
     mouse_buttons: [bool; 4],
+    modifiers: ModifiersState,
 }
 
 impl InputState {
@@ -15,14 +20,35 @@ impl InputState {
         self.mouse_pos
     }
 
+    /// Returns the world position under the cursor at the end of the last frame, or `None` if a mouse button
+    /// was pressed since, so that strokes can follow the cursor between frames wherever the camera moves.
+    pub fn get_previous_world_pos(&self) -> Option<Pos2i> {
+        self.previous_world_pos
+    }
+
+    /// Ends the current frame, remembering the world position under the cursor.
+    pub fn next_frame(&mut self, world_pos: Pos2i) {
+        self.previous_world_pos = Some(world_pos);
+    }
+
+    /// Returns the modifier keys currently held down.
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
     pub fn update_input(&mut self, event: &WindowEvent, update_mouse_buttons: bool) {
         match event {
             WindowEvent::CursorMoved { position, .. } => {
                 self.mouse_pos = (position.x as u64, position.y as u64)
             }
             WindowEvent::MouseInput { button, state, .. } if update_mouse_buttons => {
-                self.mouse_buttons[id_for_button(*button)] = *state == ElementState::Pressed
+                self.mouse_buttons[id_for_button(*button)] = *state == ElementState::Pressed;
+
+                if *state == ElementState::Pressed {
+                    self.previous_world_pos = None;
+                }
             }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
             _ => {}
         }
     }
@@ -0,0 +1,277 @@
+use serde::Deserialize;
+use std::{error, fmt, fs, io, path::Path};
+use winit::event::{ModifiersState, VirtualKeyCode};
+
+/// The default keyboard shortcuts, used when no bindings file is found.
+pub const BUILTIN_BINDINGS: &str = include_str!("../assets/keybindings.ron");
+
+/// Something the user can do with a keyboard shortcut.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum Action {
+    TogglePause,
+    /// Simulates a single step while the simulation is paused.
+    Step,
+    /// Selects the material at the given index of the materials file.
+    SelectMaterial(u8),
+    ShrinkBrush,
+    GrowBrush,
+    Undo,
+    Redo,
+    Clear,
+    Save,
+    Load,
+}
+
+/// A key along with the modifier keys which have to be held with it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Shortcut {
+    pub key: VirtualKeyCode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Shortcut {
+    /// Parses a shortcut written as the name of a key prefixed by modifiers, such as `Ctrl+Shift+Z`.
+    pub fn parse(source: &str) -> Option<Shortcut> {
+        let mut parts: Vec<&str> = source.split('+').map(str::trim).collect();
+        let key = parse_key(parts.pop()?)?;
+
+        let mut shortcut = Shortcut {
+            key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        };
+
+        for modifier in parts {
+            match modifier {
+                "Ctrl" => shortcut.ctrl = true,
+                "Shift" => shortcut.shift = true,
+                "Alt" => shortcut.alt = true,
+                _ => return None,
+            }
+        }
+
+        Some(shortcut)
+    }
+
+    /// Returns whether this shortcut is triggered by the given key pressed with the given modifiers held.
+    pub fn matches(&self, key: VirtualKeyCode, modifiers: ModifiersState) -> bool {
+        self.key == key
+            && self.ctrl == modifiers.ctrl()
+            && self.shift == modifiers.shift()
+            && self.alt == modifiers.alt()
+    }
+}
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{:?}", self.key)
+    }
+}
+
+/// An error occurring when loading a bindings file.
+#[derive(Debug)]
+pub enum BindingsError {
+    Io(io::Error),
+    Parse(ron::Error),
+    /// A binding uses a key or modifier which doesn't exist.
+    UnknownKey(String),
+}
+
+impl fmt::Display for BindingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BindingsError::Io(err) => write!(f, "i/o error: {}", err),
+            BindingsError::Parse(err) => write!(f, "invalid bindings file: {}", err),
+            BindingsError::UnknownKey(key) => write!(f, "unknown key `{}`", key),
+        }
+    }
+}
+
+impl error::Error for BindingsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            BindingsError::Io(err) => Some(err),
+            BindingsError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct BindingDef {
+    key: String,
+    action: Action,
+}
+
+/// The keyboard shortcuts of the sandbox.
+pub struct Keybindings {
+    bindings: Vec<(Shortcut, Action)>,
+}
+
+impl Keybindings {
+    /// Returns the default keyboard shortcuts.
+    pub fn builtin() -> Keybindings {
+        Keybindings::from_ron(BUILTIN_BINDINGS).expect("Builtin bindings are invalid")
+    }
+
+    /// Reads keyboard shortcuts from a bindings file.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<Keybindings, BindingsError> {
+        let source = fs::read_to_string(path).map_err(BindingsError::Io)?;
+        Keybindings::from_ron(&source)
+    }
+
+    /// Reads keyboard shortcuts written in RON.
+    pub fn from_ron(source: &str) -> Result<Keybindings, BindingsError> {
+        let defs: Vec<BindingDef> = ron::from_str(source).map_err(BindingsError::Parse)?;
+
+        let bindings = defs
+            .into_iter()
+            .map(|def| match Shortcut::parse(&def.key) {
+                Some(shortcut) => Ok((shortcut, def.action)),
+                None => Err(BindingsError::UnknownKey(def.key)),
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Keybindings { bindings })
+    }
+
+    /// Returns the action bound to the given key pressed with the given modifiers held, if any.
+    pub fn action_for(&self, key: VirtualKeyCode, modifiers: ModifiersState) -> Option<Action> {
+        self.bindings
+            .iter()
+            .find(|(shortcut, _)| shortcut.matches(key, modifiers))
+            .map(|(_, action)| *action)
+    }
+
+    /// Returns the first shortcut bound to the given action, if any.
+    pub fn shortcut_for(&self, action: Action) -> Option<Shortcut> {
+        self.bindings
+            .iter()
+            .find(|(_, bound)| *bound == action)
+            .map(|(shortcut, _)| *shortcut)
+    }
+
+    /// Returns the given label followed by the shortcut bound to the given action, if any.
+    pub fn label(&self, label: &str, action: Action) -> String {
+        match self.shortcut_for(action) {
+            Some(shortcut) => format!("{} ({})", label, shortcut),
+            None => label.to_string(),
+        }
+    }
+}
+
+macro_rules! keys {
+    ($($key:ident),* $(,)?) => {
+        /// Returns the key with the given name, as written in bindings files.
+        fn parse_key(name: &str) -> Option<VirtualKeyCode> {
+            match name {
+                $(stringify!($key) => Some(VirtualKeyCode::$key),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+keys!(
+    Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, A, B, C, D, E, F, G, H, I, J, K, L,
+    M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Escape, Space, Return, Tab, Back, Delete, Insert, Home, End, PageUp, PageDown, Left, Up, Right,
+    Down, Comma, Period, Minus, Equals, Plus, LBracket, RBracket, Semicolon, Apostrophe, Slash,
+    Backslash, Grave,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn modifiers(ctrl: bool, shift: bool, alt: bool) -> ModifiersState {
+        let mut modifiers = ModifiersState::empty();
+        modifiers.set(ModifiersState::CTRL, ctrl);
+        modifiers.set(ModifiersState::SHIFT, shift);
+        modifiers.set(ModifiersState::ALT, alt);
+        modifiers
+    }
+
+    #[test]
+    fn builtin_bindings_are_valid() {
+        let bindings = Keybindings::builtin();
+
+        assert_eq!(
+            bindings.action_for(VirtualKeyCode::Z, modifiers(true, false, false)),
+            Some(Action::Undo)
+        );
+        assert!(bindings.shortcut_for(Action::Load).is_some());
+    }
+
+    #[test]
+    fn shortcuts_are_parsed_with_their_modifiers() {
+        assert_eq!(
+            Shortcut::parse("Ctrl + Shift+Alt+F5"),
+            Some(Shortcut {
+                key: VirtualKeyCode::F5,
+                ctrl: true,
+                shift: true,
+                alt: true,
+            })
+        );
+        assert_eq!(
+            Shortcut::parse("Space"),
+            Some(Shortcut {
+                key: VirtualKeyCode::Space,
+                ctrl: false,
+                shift: false,
+                alt: false,
+            })
+        );
+        assert_eq!(
+            Shortcut::parse("Shift+Ctrl+Z").unwrap().to_string(),
+            "Ctrl+Shift+Z"
+        );
+
+        assert_eq!(Shortcut::parse("Super+Z"), None);
+        assert_eq!(Shortcut::parse("Ctrl+"), None);
+        assert_eq!(Shortcut::parse("Ctrl+Shift"), None);
+    }
+
+    #[test]
+    fn modifiers_have_to_match_exactly() {
+        let bindings = Keybindings::from_ron(
+            r#"[(key: "Ctrl+Z", action: Undo), (key: "Ctrl+Shift+Z", action: Redo)]"#,
+        )
+        .unwrap();
+
+        let action =
+            |ctrl, shift, alt| bindings.action_for(VirtualKeyCode::Z, modifiers(ctrl, shift, alt));
+
+        assert_eq!(action(true, false, false), Some(Action::Undo));
+        assert_eq!(action(true, true, false), Some(Action::Redo));
+        assert_eq!(action(false, false, false), None);
+        assert_eq!(action(true, false, true), None);
+        assert_eq!(action(true, true, true), None);
+    }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let result = Keybindings::from_ron(
+            r#"[(key: "Ctrl+Z", action: Undo), (key: "Ctrl+Banana", action: Redo)]"#,
+        );
+
+        match result {
+            Err(BindingsError::UnknownKey(key)) => assert_eq!(key, "Ctrl+Banana"),
+            _ => panic!("expected an unknown key error"),
+        }
+    }
+}
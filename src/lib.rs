@@ -0,0 +1,8 @@
+//! The `poussière` falling sand simulation.
+//!
+//! This crate exposes the simulation grid without any windowing dependency so it can be
+//! driven headlessly. The interactive frontend lives behind the `gui` cargo feature.
+
+mod world;
+
+pub use world::*;
@@ -1,12 +1,22 @@
+#[cfg(feature = "gui")]
 mod app;
+#[cfg(feature = "gui")]
+mod camera;
+mod cli;
+#[cfg(feature = "gui")]
 mod gui;
+#[cfg(feature = "gui")]
+mod history;
+#[cfg(feature = "gui")]
 mod input;
-mod world;
+#[cfg(feature = "gui")]
+mod keybindings;
+#[cfg(feature = "gui")]
+mod tools;
 
-pub use world::*;
-
-use std::error;
+use std::{env, error, process};
 
+#[cfg(feature = "gui")]
 use winit::{
     dpi::{PhysicalSize, Size},
     event::Event,
@@ -15,7 +25,27 @@ use winit::{
     window::WindowBuilder,
 };
 
-fn main() -> Result<(), Box<dyn error::Error>> {
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        None => run_gui(),
+        Some("run") => cli::run(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", cli::USAGE);
+            Ok(())
+        }
+        Some(command) => Err(format!("unknown command `{}`\n\n{}", command, cli::USAGE).into()),
+    };
+
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+#[cfg(feature = "gui")]
+fn run_gui() -> Result<(), Box<dyn error::Error>> {
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
         .with_inner_size(Size::Physical(PhysicalSize::new(
@@ -23,7 +53,6 @@ fn main() -> Result<(), Box<dyn error::Error>> {
             app::WINDOW_HEIGHT as u32,
         )))
         .with_title("poussière")
-        .with_resizable(false)
         .build(&event_loop)?;
 
     let mut app = app::AppState::create(&window)?;
@@ -36,6 +65,7 @@ fn main() -> Result<(), Box<dyn error::Error>> {
         Event::RedrawRequested(_) => app.draw(&window),
         Event::WindowEvent { event, .. } => match event {
             WindowEvent::CloseRequested => {
+                app.close();
                 *control_flow = winit::event_loop::ControlFlow::Exit;
             }
             _ => app.handle_event(&evt, &window),
@@ -43,3 +73,8 @@ fn main() -> Result<(), Box<dyn error::Error>> {
         _ => (),
     });
 }
+
+#[cfg(not(feature = "gui"))]
+fn run_gui() -> Result<(), Box<dyn error::Error>> {
+    Err(format!("built without the `gui` feature\n\n{}", cli::USAGE).into())
+}
@@ -0,0 +1,290 @@
+use poussiere::{Grid, Pos2i};
+use std::collections::VecDeque;
+
+/// How the cells the brush is applied to are picked.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Tool {
+    /// The brush is applied around the cursor as long as the button is held.
+    #[default]
+    Freehand,
+    /// A line as thick as the brush, dragged from where the button was pressed.
+    Line,
+    /// The outline of a rectangle dragged from one corner to the other.
+    Rectangle,
+    /// A filled rectangle dragged from one corner to the other.
+    FilledRectangle,
+    /// A filled ellipse fitting in the rectangle dragged from one corner to the other.
+    Ellipse,
+    /// The connected region of tiles of the material under the cursor.
+    FloodFill,
+}
+
+/// The outline of the area the brush covers around the cursor.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum BrushShape {
+    #[default]
+    Circle,
+    Square,
+}
+
+impl Tool {
+    /// Returns whether the tool draws a shape dragged from where the button was pressed,
+    /// which is only applied once it is released.
+    pub fn is_shape(self) -> bool {
+        matches!(
+            self,
+            Tool::Line | Tool::Rectangle | Tool::FilledRectangle | Tool::Ellipse
+        )
+    }
+
+    /// Returns the cells of the shape dragged between the given positions, each one once.
+    /// Lines are drawn with the given brush.
+    pub fn shape_cells(
+        self,
+        from: Pos2i,
+        to: Pos2i,
+        brush_size: u64,
+        brush_shape: BrushShape,
+    ) -> Vec<Pos2i> {
+        let min: Pos2i = (from.x.min(to.x), from.y.min(to.y)).into();
+        let max: Pos2i = (from.x.max(to.x), from.y.max(to.y)).into();
+        let area = (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| (x, y).into()));
+
+        let mut cells: Vec<Pos2i> = match self {
+            Tool::Line => from
+                .line_to(to)
+                .flat_map(|position| brush_cells(position, brush_size, brush_shape))
+                .collect(),
+            Tool::Rectangle => area
+                .filter(|cell: &Pos2i| {
+                    cell.x == min.x || cell.x == max.x || cell.y == min.y || cell.y == max.y
+                })
+                .collect(),
+            Tool::FilledRectangle => area.collect(),
+            Tool::Ellipse => {
+                let center = ((min.x + max.x) as f32 / 2.0, (min.y + max.y) as f32 / 2.0);
+                let radii = (
+                    (max.x - min.x) as f32 / 2.0 + 0.5,
+                    (max.y - min.y) as f32 / 2.0 + 0.5,
+                );
+
+                area.filter(|cell: &Pos2i| {
+                    let dx = (cell.x as f32 - center.0) / radii.0;
+                    let dy = (cell.y as f32 - center.1) / radii.1;
+                    dx * dx + dy * dy <= 1.0
+                })
+                .collect()
+            }
+            Tool::Freehand | Tool::FloodFill => Vec::new(),
+        };
+
+        cells.sort_by_key(|cell| (cell.y, cell.x));
+        cells.dedup();
+        cells
+    }
+}
+
+/// Returns the cells covered by a brush of the given size and shape centered on the given position.
+/// Brushes of size 1 cover a single cell.
+pub fn brush_cells(center: Pos2i, size: u64, shape: BrushShape) -> Vec<Pos2i> {
+    let radius = size as i64;
+    let mut cells = Vec::new();
+
+    for dx in -radius..radius + 1 {
+        for dy in -radius..radius + 1 {
+            let inside = match shape {
+                BrushShape::Circle => dx * dx + dy * dy < radius * radius,
+                BrushShape::Square => dx.abs() < radius && dy.abs() < radius,
+            };
+
+            if !inside {
+                continue;
+            };
+
+            cells.push(center + (dx, dy).into());
+        }
+    }
+
+    cells
+}
+
+/// Returns the cells connected to the given one by tiles of the same material, sides touching.
+pub fn flood_region(world: &Grid, start: Pos2i) -> Vec<Pos2i> {
+    let tile_type = match world.get_tile(start) {
+        Some(tile) => tile.tile_type,
+        None => return Vec::new(),
+    };
+
+    let size = world.size();
+    let mut visited = vec![false; (size.0 * size.1) as usize];
+    let mut queue = VecDeque::new();
+    let mut region = Vec::new();
+
+    visited[(start.y as u64 * size.0 + start.x as u64) as usize] = true;
+    queue.push_back(start);
+
+    while let Some(position) = queue.pop_front() {
+        region.push(position);
+
+        for offset in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let neighbour = position + offset.into();
+
+            match world.get_tile(neighbour) {
+                Some(tile) if tile.tile_type == tile_type => {
+                    let idx = (neighbour.y as u64 * size.0 + neighbour.x as u64) as usize;
+
+                    if !visited[idx] {
+                        visited[idx] = true;
+                        queue.push_back(neighbour);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    region
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poussiere::TILE_AIR;
+
+    fn cells(positions: &[(i64, i64)]) -> Vec<Pos2i> {
+        positions
+            .iter()
+            .map(|position| (*position).into())
+            .collect()
+    }
+
+    fn shape(tool: Tool, from: (i64, i64), to: (i64, i64), brush_size: u64) -> Vec<Pos2i> {
+        tool.shape_cells(from.into(), to.into(), brush_size, BrushShape::Circle)
+    }
+
+    #[test]
+    fn lines_follow_the_drag() {
+        assert_eq!(
+            shape(Tool::Line, (3, 1), (0, 1), 1),
+            cells(&[(0, 1), (1, 1), (2, 1), (3, 1)])
+        );
+        assert_eq!(
+            shape(Tool::Line, (0, 0), (2, 2), 1),
+            cells(&[(0, 0), (1, 1), (2, 2)])
+        );
+    }
+
+    #[test]
+    fn thick_lines_cover_each_cell_once() {
+        let line = shape(Tool::Line, (0, 0), (5, 0), 2);
+
+        // a 3 cells thick band, reaching one cell past both ends
+        assert_eq!(line.len(), (6 + 2) * 3);
+        assert!(line.windows(2).all(|pair| pair[0] != pair[1]));
+    }
+
+    #[test]
+    fn rectangles_are_outlined_or_filled() {
+        let outline = cells(&[
+            (0, 0),
+            (1, 0),
+            (2, 0),
+            (3, 0),
+            (0, 1),
+            (3, 1),
+            (0, 2),
+            (1, 2),
+            (2, 2),
+            (3, 2),
+        ]);
+
+        assert_eq!(shape(Tool::Rectangle, (0, 0), (3, 2), 1), outline);
+        assert_eq!(shape(Tool::Rectangle, (3, 2), (0, 0), 1), outline);
+        assert_eq!(shape(Tool::FilledRectangle, (3, 0), (0, 2), 1).len(), 12);
+    }
+
+    #[test]
+    fn ellipses_fit_in_the_dragged_rectangle() {
+        let ellipse = shape(Tool::Ellipse, (0, 0), (6, 4), 1);
+
+        assert!(ellipse.contains(&(3, 2).into()));
+        assert!(ellipse.contains(&(0, 2).into()) && ellipse.contains(&(6, 2).into()));
+        assert!(ellipse.contains(&(3, 0).into()) && ellipse.contains(&(3, 4).into()));
+        assert!(!ellipse.contains(&(0, 0).into()) && !ellipse.contains(&(6, 4).into()));
+        assert!(ellipse
+            .iter()
+            .all(|cell| (0..=6).contains(&cell.x) && (0..=4).contains(&cell.y)));
+    }
+
+    #[test]
+    fn degenerate_drags_cover_the_cells_dragged_over() {
+        for tool in [
+            Tool::Line,
+            Tool::Rectangle,
+            Tool::FilledRectangle,
+            Tool::Ellipse,
+        ] {
+            assert_eq!(shape(tool, (4, -2), (4, -2), 1), cells(&[(4, -2)]));
+            assert_eq!(
+                shape(tool, (1, 7), (3, 7), 1),
+                cells(&[(1, 7), (2, 7), (3, 7)])
+            );
+        }
+    }
+
+    #[test]
+    fn tools_without_a_shape_have_no_cells() {
+        for tool in [Tool::Freehand, Tool::FloodFill] {
+            assert!(!tool.is_shape());
+            assert!(shape(tool, (0, 0), (5, 5), 3).is_empty());
+        }
+    }
+
+    #[test]
+    fn brushes_cover_their_shape() {
+        let center = (10, -4).into();
+
+        assert_eq!(
+            brush_cells(center, 1, BrushShape::Circle),
+            cells(&[(10, -4)])
+        );
+        assert_eq!(
+            brush_cells(center, 1, BrushShape::Square),
+            cells(&[(10, -4)])
+        );
+        // circles leave out the corners of the square
+        assert_eq!(brush_cells(center, 4, BrushShape::Circle).len(), 7 * 7 - 4);
+        assert_eq!(brush_cells(center, 4, BrushShape::Square).len(), 7 * 7);
+
+        for cell in brush_cells(center, 4, BrushShape::Square) {
+            assert!((cell.x - 10).abs() <= 3 && (cell.y + 4).abs() <= 3);
+        }
+    }
+
+    #[test]
+    fn flood_regions_stop_at_the_edges_of_the_grid() {
+        let mut grid = Grid::new((5, 4));
+        let wall = grid.materials().find("Wall").unwrap();
+        let tile = grid.materials().create_tile(wall, 0);
+
+        // a wall splitting the grid in two, with a hole at the bottom
+        for y in 0..3 {
+            grid.set_tile((2, y).into(), tile);
+        }
+
+        let region = flood_region(&grid, (0, 0).into());
+        assert_eq!(region.len(), 5 * 4 - 3);
+        assert!(region.contains(&(4, 0).into()) && region.contains(&(4, 3).into()));
+        assert!(region
+            .iter()
+            .all(|cell| grid.get_tile(*cell).unwrap() == TILE_AIR));
+
+        let mut walls = flood_region(&grid, (2, 2).into());
+        walls.sort_by_key(|cell| cell.y);
+        assert_eq!(walls, cells(&[(2, 0), (2, 1), (2, 2)]));
+
+        for outside in [(-1, 0), (5, 0), (0, 4), (0, -1)] {
+            assert!(flood_region(&grid, outside.into()).is_empty());
+        }
+    }
+}
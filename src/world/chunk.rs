@@ -0,0 +1,177 @@
+use super::*;
+
+/// The width and height of the chunks the grid is split into, in tiles.
+pub const CHUNK_SIZE: i64 = 32;
+
+/// A rectangle of tiles, both corners included.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rect {
+    pub min: Pos2i,
+    pub max: Pos2i,
+}
+
+impl Rect {
+    /// Returns the smallest rectangle containing this one and the given position.
+    pub fn including(self, position: Pos2i) -> Rect {
+        Rect {
+            min: (self.min.x.min(position.x), self.min.y.min(position.y)).into(),
+            max: (self.max.x.max(position.x), self.max.y.max(position.y)).into(),
+        }
+    }
+
+    /// Returns the tiles shared by this rectangle and the given one, if any.
+    pub fn intersection(self, other: Rect) -> Option<Rect> {
+        let min: Pos2i = (self.min.x.max(other.min.x), self.min.y.max(other.min.y)).into();
+        let max: Pos2i = (self.max.x.min(other.max.x), self.max.y.min(other.max.y)).into();
+
+        if min.x <= max.x && min.y <= max.y {
+            Some(Rect { min, max })
+        } else {
+            None
+        }
+    }
+
+    pub fn contains(&self, position: Pos2i) -> bool {
+        (self.min.x..=self.max.x).contains(&position.x)
+            && (self.min.y..=self.max.y).contains(&position.y)
+    }
+
+    /// Returns an iterator over the positions of this rectangle, row by row.
+    pub fn positions(self) -> impl Iterator<Item = Pos2i> {
+        (self.min.y..=self.max.y)
+            .flat_map(move |y| (self.min.x..=self.max.x).map(move |x| (x, y).into()))
+    }
+}
+
+/// A square region of the grid keeping track of which of its tiles need to be simulated.
+/// Chunks whose tiles didn't change during a step are asleep and skipped by the next one.
+#[derive(Clone, Debug)]
+pub(super) struct Chunk {
+    /// The tiles of the chunk.
+    pub(super) bounds: Rect,
+    /// The tiles simulated during the current step, `None` if the chunk is asleep.
+    pub(super) dirty: Option<Rect>,
+    /// The tiles to simulate during the next step, grown as tiles around them change.
+    pub(super) next_dirty: Option<Rect>,
+    /// Whether tiles of the chunk changed since the frontend last redrew it.
+    pub(super) changed: bool,
+}
+
+/// Splits a grid of the given size into chunks, row by row.
+pub(super) fn create_chunks(size: (u64, u64)) -> impl Iterator<Item = Chunk> {
+    let (width, height) = (size.0 as i64, size.1 as i64);
+
+    (0..height).step_by(CHUNK_SIZE as usize).flat_map(move |y| {
+        (0..width).step_by(CHUNK_SIZE as usize).map(move |x| Chunk {
+            bounds: Rect {
+                min: (x, y).into(),
+                max: (
+                    (x + CHUNK_SIZE).min(width) - 1,
+                    (y + CHUNK_SIZE).min(height) - 1,
+                )
+                    .into(),
+            },
+            dirty: None,
+            next_dirty: None,
+            changed: false,
+        })
+    })
+}
+
+impl Grid {
+    /// Returns the number of chunks simulated during the last step.
+    pub fn awake_chunks(&self) -> usize {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.dirty.is_some())
+            .count()
+    }
+
+    /// Returns the bounds of the chunks whose tiles changed since the last call,
+    /// so frontends only have to redraw these.
+    pub fn take_changed_chunks(&mut self) -> Vec<Rect> {
+        self.chunks
+            .iter_mut()
+            .filter(|chunk| chunk.changed)
+            .map(|chunk| {
+                chunk.changed = false;
+                chunk.bounds
+            })
+            .collect()
+    }
+
+    /// Returns the number of chunks per row of the grid.
+    pub(super) fn chunks_per_row(&self) -> usize {
+        (self.size().0 as usize + CHUNK_SIZE as usize - 1) / CHUNK_SIZE as usize
+    }
+
+    pub(super) fn chunk_index(&self, position: Pos2i) -> usize {
+        (position.y / CHUNK_SIZE) as usize * self.chunks_per_row()
+            + (position.x / CHUNK_SIZE) as usize
+    }
+
+    /// Schedules the tiles around the given position to be simulated during the next step.
+    pub(super) fn wake(&mut self, position: Pos2i) {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let neighbour = position + (dx, dy).into();
+
+                if self.get_tile(neighbour).is_none() {
+                    continue;
+                }
+
+                let idx = self.chunk_index(neighbour);
+                let chunk = &mut self.chunks[idx];
+
+                chunk.next_dirty = Some(match chunk.next_dirty {
+                    Some(rect) => rect.including(neighbour),
+                    None => Rect {
+                        min: neighbour,
+                        max: neighbour,
+                    },
+                });
+            }
+        }
+    }
+
+    /// Schedules the tiles of the given rectangle to be simulated during the next step.
+    pub(super) fn wake_rect(&mut self, rect: Rect) {
+        let per_row = self.chunks_per_row();
+        let first = self.chunk_index(rect.min);
+        let last = self.chunk_index(rect.max);
+
+        for row in (first / per_row)..=(last / per_row) {
+            for column in (first % per_row)..=(last % per_row) {
+                let chunk = &mut self.chunks[row * per_row + column];
+
+                if let Some(woken) = chunk.bounds.intersection(rect) {
+                    chunk.next_dirty = Some(match chunk.next_dirty {
+                        Some(rect) => rect.including(woken.min).including(woken.max),
+                        None => woken,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Flags the chunk of the given position as changed for frontends.
+    pub(super) fn mark_changed(&mut self, position: Pos2i) {
+        let idx = self.chunk_index(position);
+        self.chunks[idx].changed = true;
+    }
+
+    /// Wakes every chunk up and flags it as changed, after the whole grid got replaced.
+    pub(super) fn wake_all(&mut self) {
+        for chunk in &mut self.chunks {
+            chunk.next_dirty = Some(chunk.bounds);
+            chunk.changed = true;
+        }
+    }
+
+    /// Starts a step: the regions woken up during the last one become the ones to simulate.
+    pub(super) fn swap_dirty_rects(&mut self) {
+        for chunk in &mut self.chunks {
+            chunk.dirty = chunk.next_dirty.take();
+        }
+    }
+}
@@ -1,30 +1,103 @@
 use super::*;
-use std::{iter::Enumerate, slice::Iter, vec::Vec};
+use rand::{Rng, SeedableRng};
+use std::{iter::Enumerate, slice::Iter, sync::Arc, vec::Vec};
 
 /// The falling sand simulation grid.
 /// (X, Y) coordinates in the grid extend respectively to the right and to the bottom
 #[derive(Clone)]
 pub struct Grid {
-    tiles: Vec<Tile>,
+    pub(super) tiles: Vec<Tile>,
     size: (u64, u64),
+    seed: u64,
+    rng: SimRng,
+    materials: Arc<MaterialRegistry>,
+    pub(super) particles: Vec<Particle>,
+    pub(super) chunks: Vec<Chunk>,
+    /// Whether each cell was written to during the current step, tiles moving into a cell
+    /// being updated only once per step.
+    pub(super) updated: Vec<bool>,
+    /// The number of steps simulated, whose parity gives the horizontal scan direction.
+    pub(super) tick: u64,
+    /// The heat flowing into each tile during a step, which is zero outside of it.
+    heat_flow: Vec<f32>,
+    /// The (center, radius, power) of the explosions caused during the current step, which are set off
+    /// once every tile was updated.
+    pub(super) explosions: Vec<(Pos2i, f32, f32)>,
+    /// The windows parallel steps simulate chunks on, kept between steps so they aren't reallocated.
+    pub(super) windows: WindowPool,
 }
 
+/// The fraction of their temperature difference two perfectly conducting neighbours exchange each step.
+/// A tile has at most four neighbours so it never exchanges more than half of its heat.
+const HEAT_TRANSFER_RATE: f32 = 0.125;
+
+/// The temperature change below which a tile is considered at thermal rest, letting its chunk fall asleep.
+const HEAT_EPSILON: f32 = 0.01;
+
+/// The speed debris is flung at per unit of blast power left once it hit it, in cells per step.
+const DEBRIS_SPEED: f32 = 1.5;
+
+/// The seed used by grids created with [`Grid::new`].
+pub const DEFAULT_SEED: u64 = 0;
+
 pub const TILE_AIR: Tile = Tile {
     variant: 0,
-    tile_type: TileType::Air,
+    tile_type: TileType::AIR,
+    temperature: AMBIENT_TEMPERATURE,
+    burning: false,
+    velocity: Vec2f { x: 0.0, y: 0.0 },
 };
 
-#[derive(Clone, Copy)]
+/// Which part of a grid keeps its content in place when it is resized.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ResizeAnchor {
+    /// Tiles keep their position, the grid growing or shrinking on its right and bottom sides.
+    #[default]
+    TopLeft,
+    /// Tiles stay centered, the grid growing or shrinking evenly on every side.
+    Center,
+}
+
+/// A single cell of the simulation grid.
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct Tile {
     pub variant: u8,
     pub tile_type: TileType,
+    /// The temperature of the tile in degrees Celsius.
+    pub temperature: f32,
+    /// Whether the tile is on fire.
+    pub burning: bool,
+    /// The speed of the tile in cells per step, y pointing down.
+    pub velocity: Vec2f,
 }
 
 impl Grid {
+    /// Creates a new grid of the given (width, height) filled with air.
     pub fn new(size: (u64, u64)) -> Grid {
+        Grid::with_seed(size, DEFAULT_SEED)
+    }
+
+    /// Creates a new grid filled with air whose simulation is driven by the given seed.
+    /// Two grids with the same seed and the same tiles always evolve identically.
+    pub fn with_seed(size: (u64, u64), seed: u64) -> Grid {
+        Grid::with_materials(size, seed, MaterialRegistry::builtin())
+    }
+
+    /// Creates a new grid filled with air simulating the materials of the given registry.
+    pub fn with_materials(size: (u64, u64), seed: u64, materials: Arc<MaterialRegistry>) -> Grid {
         Grid {
             tiles: vec![TILE_AIR; (size.0 * size.1) as usize],
             size,
+            seed,
+            rng: SimRng::seed_from_u64(seed),
+            materials,
+            particles: Vec::new(),
+            updated: vec![false; (size.0 * size.1) as usize],
+            tick: 0,
+            chunks: create_chunks(size).collect(),
+            heat_flow: vec![0.0; (size.0 * size.1) as usize],
+            explosions: Vec::new(),
+            windows: WindowPool::default(),
         }
     }
 
@@ -32,7 +105,29 @@ impl Grid {
         self.size
     }
 
-    fn index_of(&self, position: Pos2i) -> Option<usize> {
+    /// Returns the seed this grid was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Reseeds the simulation, restarting its random number generator from the given seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = SimRng::seed_from_u64(seed);
+    }
+
+    /// Returns the materials this grid is simulated with.
+    pub fn materials(&self) -> &Arc<MaterialRegistry> {
+        &self.materials
+    }
+
+    /// Returns the random number generator of the simulation.
+    /// Frontends should draw from it when placing tiles so edits stay reproducible.
+    pub fn rng(&mut self) -> &mut SimRng {
+        &mut self.rng
+    }
+
+    pub(super) fn index_of(&self, position: Pos2i) -> Option<usize> {
         if (0..self.size.0 as i64).contains(&position.x)
             && (0..self.size.1 as i64).contains(&position.y)
         {
@@ -42,16 +137,22 @@ impl Grid {
         }
     }
 
+    /// Sets the tile at the given position. Out of bounds positions are ignored.
     pub fn set_tile(&mut self, pos: Pos2i, tile: Tile) {
         if let Some(idx) = self.index_of(pos) {
             self.tiles[idx] = tile;
+            self.updated[idx] = true;
+            self.wake(pos);
+            self.mark_changed(pos);
         }
     }
 
+    /// Returns the tile at the given position or `None` if it is out of bounds.
     pub fn get_tile(&self, pos: Pos2i) -> Option<Tile> {
         self.index_of(pos).map(|idx| self.tiles[idx])
     }
 
+    /// Returns an iterator over every tile of the grid along with its position, row by row.
     pub fn iter_tiles(&self) -> WorldIter<'_> {
         WorldIter::from_world(self)
     }
@@ -62,28 +163,365 @@ impl Grid {
 
     pub fn restore(&mut self, tiles: Vec<Tile>) {
         self.tiles.copy_from_slice(&tiles);
+        self.particles.clear();
+        self.wake_all();
     }
 
-    pub fn step(&mut self) {
-        let mut next_gen = self.clone();
+    /// Changes the (width, height) of the grid, keeping the tiles which still fit in place relative to the
+    /// given anchor. New cells are filled with air and particles are moved along with the tiles.
+    pub fn resize(&mut self, size: (u64, u64), anchor: ResizeAnchor) {
+        let shift: Pos2i = match anchor {
+            ResizeAnchor::TopLeft => (0, 0).into(),
+            ResizeAnchor::Center => (
+                (size.0 as i64 - self.size.0 as i64) / 2,
+                (size.1 as i64 - self.size.1 as i64) / 2,
+            )
+                .into(),
+        };
+
+        let mut resized = Grid::with_materials(size, self.seed, self.materials.clone());
 
         for (position, tile) in self.iter_tiles() {
-            match tile.tile_type {
-                TileType::Sand => update_falling_tile(&mut next_gen, position, tile),
-                TileType::Dirt => update_falling_tile(&mut next_gen, position, tile),
-                TileType::Water => update_water(&mut next_gen, position, tile),
-                TileType::Lava => update_lava(&mut next_gen, position, tile),
-                TileType::Stone => update_falling_tile(&mut next_gen, position, tile),
-                TileType::Acid => update_acid(&mut next_gen, position, tile),
+            if let Some(idx) = resized.index_of(position + shift) {
+                resized.tiles[idx] = *tile;
+            }
+        }
+
+        for particle in &self.particles {
+            let particle = Particle {
+                position: (
+                    particle.position.x + shift.x as f32,
+                    particle.position.y + shift.y as f32,
+                )
+                    .into(),
+                ..*particle
+            };
+            let cell = particle.cell();
+
+            if (0..size.0 as i64).contains(&cell.x) && cell.y < size.1 as i64 {
+                resized.particles.push(particle);
+            }
+        }
+
+        resized.rng = self.rng.clone();
+        resized.tick = self.tick;
+        resized.wake_all();
+
+        *self = resized;
+    }
+
+    /// Advances the simulation by one tick.
+    ///
+    /// Chunks are updated in parallel following a checkerboard schedule: the step is split in four phases
+    /// during which only chunks two chunks apart are simulated, each one from the tiles around it. Every chunk
+    /// draws from its own random number generator so the outcome doesn't depend on the number of threads.
+    /// Tiles within a chunk are updated in place, row by row from the bottom, in alternating directions each tick.
+    /// Only the dirty regions of awake chunks are simulated, chunks falling asleep once their tiles settle.
+    pub fn step(&mut self) {
+        self.begin_step();
+
+        let seed = self.rng.gen();
+        self.update_chunks_parallel(seed);
+
+        self.end_step();
+    }
+
+    /// Advances the simulation by one tick on the calling thread only.
+    ///
+    /// Tiles are updated in place, row by row from the bottom of the whole grid so falling tiles move into rows
+    /// which were already updated. This evolves differently from [`Grid::step`] but is just as deterministic.
+    pub fn step_sequential(&mut self) {
+        self.begin_step();
+
+        let materials = self.materials.clone();
+        let mut rng = self.rng.clone();
+        let left_to_right = self.tick % 2 == 0;
+        // grids without any width have no chunk rows to update
+        let chunks_per_row = self.chunks_per_row().max(1);
+
+        for row_start in (0..self.chunks.len()).step_by(chunks_per_row).rev() {
+            let row_bounds = self.chunks[row_start].bounds;
+            let row_length = chunks_per_row.min(self.chunks.len() - row_start);
+
+            for y in (row_bounds.min.y..=row_bounds.max.y).rev() {
+                for i in 0..row_length {
+                    let chunk = if left_to_right { i } else { row_length - 1 - i };
+
+                    let rect = match self.chunks[row_start + chunk].dirty {
+                        Some(rect) if (rect.min.y..=rect.max.y).contains(&y) => rect,
+                        _ => continue,
+                    };
+
+                    self.update_row(&mut rng, &materials, rect, y, left_to_right);
+                }
+            }
+        }
+
+        self.rng = rng;
+        self.end_step();
+    }
+
+    /// Picks the regions to simulate and conducts heat, before tiles move.
+    fn begin_step(&mut self) {
+        self.swap_dirty_rects();
+
+        // every tile written to since the last step is within the new dirty regions
+        for chunk in 0..self.chunks.len() {
+            if let Some(rect) = self.chunks[chunk].dirty {
+                for position in rect.positions() {
+                    let idx = self.index_of(position).unwrap();
+                    self.updated[idx] = false;
+                }
+            }
+        }
+
+        self.update_heat();
+    }
+
+    fn end_step(&mut self) {
+        for (center, radius, power) in std::mem::take(&mut self.explosions) {
+            self.explode(center, radius, power);
+        }
+
+        self.tick += 1;
+        self.update_particles();
+    }
+
+    /// Turns this grid into an empty one of the given size simulated from the given tick,
+    /// reusing its buffers.
+    pub(super) fn reset(&mut self, size: (u64, u64), tick: u64) {
+        let count = (size.0 * size.1) as usize;
+
+        self.size = size;
+        self.tick = tick;
+        self.tiles.clear();
+        self.tiles.resize(count, TILE_AIR);
+        self.updated.clear();
+        self.updated.resize(count, false);
+        self.heat_flow.clear();
+        self.heat_flow.resize(count, 0.0);
+        self.chunks.clear();
+        self.chunks.extend(create_chunks(size));
+        self.particles.clear();
+        self.explosions.clear();
+    }
+
+    /// Updates the tiles of the given rectangle, row by row from the bottom.
+    pub(super) fn update_rect(
+        &mut self,
+        rng: &mut SimRng,
+        materials: &MaterialRegistry,
+        rect: Rect,
+        left_to_right: bool,
+    ) {
+        for y in (rect.min.y..=rect.max.y).rev() {
+            self.update_row(rng, materials, rect, y, left_to_right);
+        }
+    }
+
+    /// Updates the tiles of the given row within the given rectangle.
+    fn update_row(
+        &mut self,
+        rng: &mut SimRng,
+        materials: &MaterialRegistry,
+        rect: Rect,
+        y: i64,
+        left_to_right: bool,
+    ) {
+        for j in 0..=(rect.max.x - rect.min.x) {
+            let x = if left_to_right {
+                rect.min.x + j
+            } else {
+                rect.max.x - j
+            };
+
+            self.update_tile(rng, materials, (x, y).into());
+        }
+    }
+
+    fn update_tile(&mut self, rng: &mut SimRng, materials: &MaterialRegistry, position: Pos2i) {
+        let idx = self.index_of(position).unwrap();
+        let tile = self.tiles[idx];
+
+        if tile.tile_type == TileType::AIR || self.updated[idx] {
+            return;
+        }
+
+        let material = materials.get(tile.tile_type);
+
+        // tiles which can change without moving keep their chunk awake
+        if tile.burning || material.is_restless() {
+            self.wake(position);
+        }
+
+        if decay(self, rng, position, &tile, material) {
+            return;
+        }
+
+        if tile.burning && burn(self, rng, position, &tile, material) {
+            return;
+        }
+
+        ignite(self, rng, position, material);
+
+        if react(self, rng, position, &tile, material) {
+            return;
+        }
+
+        match material.state {
+            MaterialState::Powder => update_falling_tile(self, rng, position, &tile),
+            MaterialState::Liquid => update_fluid(self, rng, position, &tile),
+            MaterialState::Gas => update_gas(self, rng, position, &tile),
+            _ => {}
+        }
+    }
+
+    /// Blows up the tiles around the given center.
+    /// The blast power decreases linearly from the center to the radius of the explosion. Tiles whose material
+    /// blast resistance is lower than the blast are destroyed while the others are flung outward as particles
+    /// if they can move, flammable ones catching fire.
+    pub fn explode(&mut self, center: Pos2i, radius: f32, power: f32) {
+        if radius <= 0.0 {
+            return;
+        }
+
+        let reach = radius.ceil() as i64;
+        let mut hits = Vec::new();
+
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                if distance <= radius {
+                    hits.push(((dx, dy), distance));
+                }
+            }
+        }
+
+        let materials = self.materials.clone();
+
+        for (offset, distance) in hits {
+            let position = center + offset.into();
+
+            let tile = match self.get_tile(position) {
+                Some(tile) if tile.tile_type != TileType::AIR => tile,
+                _ => continue,
+            };
+
+            let material = materials.get(tile.tile_type);
+            let blast = power * (1.0 - distance / radius);
+
+            if blast > material.blast_resistance {
+                self.set_tile(position, TILE_AIR);
+                continue;
+            }
+
+            let tile = Tile {
+                burning: tile.burning || material.flammability > 0.0,
+                ..tile
+            };
+            self.set_tile(position, tile);
+
+            match material.state {
+                MaterialState::Solid | MaterialState::Powder | MaterialState::Liquid
+                    if distance > 0.0 =>
+                {
+                    let speed = blast * DEBRIS_SPEED / distance;
+                    let velocity = (offset.0 as f32 * speed, offset.1 as f32 * speed);
+                    self.launch_tile(position, velocity.into());
+                }
                 _ => {}
             }
         }
+    }
+
+    /// Conducts heat between neighbouring tiles of awake chunks and applies the resulting phase changes.
+    /// Chunks stay awake as long as the temperature of their tiles changes noticeably.
+    fn update_heat(&mut self) {
+        let materials = self.materials.clone();
+        let mut flows = std::mem::take(&mut self.heat_flow);
+
+        let awake: Vec<Rect> = self
+            .chunks
+            .iter()
+            .filter(|chunk| chunk.dirty.is_some())
+            .map(|chunk| chunk.bounds)
+            .collect();
+
+        let is_awake = |grid: &Grid, position: Pos2i| {
+            grid.index_of(position).is_some()
+                && grid.chunks[grid.chunk_index(position)].dirty.is_some()
+        };
+
+        for bounds in &awake {
+            for position in bounds.positions() {
+                let idx = self.index_of(position).unwrap();
+                let tile = self.tiles[idx];
+                let material = materials.get(tile.tile_type);
+
+                // each pair of neighbours exchanges heat once so that heat is conserved
+                let mut exchange = |other_idx: usize| {
+                    let other = self.tiles[other_idx];
+                    let conductivity = (material.conductivity
+                        * materials.get(other.tile_type).conductivity)
+                        .sqrt();
+                    let flow =
+                        HEAT_TRANSFER_RATE * conductivity * (other.temperature - tile.temperature);
+
+                    flows[idx] += flow;
+                    flows[other_idx] -= flow;
+                };
+
+                // pairs are exchanged by their left or upper tile unless its chunk is asleep
+                for offset in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+                    let neighbour = position + offset.into();
+
+                    if let Some(other_idx) = self.index_of(neighbour) {
+                        if offset.0 + offset.1 > 0 || !is_awake(self, neighbour) {
+                            exchange(other_idx);
+                        }
+                    }
+                }
+
+                flows[idx] += (AMBIENT_TEMPERATURE - tile.temperature) * material.dissipation;
+            }
+        }
+
+        for bounds in &awake {
+            let around = Rect {
+                min: bounds.min + (-1, -1).into(),
+                max: bounds.max + (1, 1).into(),
+            };
+
+            for position in around.positions() {
+                let idx = match self.index_of(position) {
+                    Some(idx) => idx,
+                    None => continue,
+                };
+
+                let flow = std::mem::take(&mut flows[idx]);
+                let tile = &mut self.tiles[idx];
+                tile.temperature += flow;
+
+                let phase_change = materials.get(tile.tile_type).phase_change(tile.temperature);
+
+                if let Some(tile_type) = phase_change {
+                    tile.tile_type = tile_type;
+                    self.mark_changed(position);
+                }
+
+                if flow.abs() > HEAT_EPSILON || phase_change.is_some() {
+                    self.wake(position);
+                }
+            }
+        }
 
-        self.tiles.copy_from_slice(&next_gen.tiles);
+        self.heat_flow = flows;
     }
 
     pub fn clear(&mut self) {
         self.tiles.iter_mut().for_each(|tile| *tile = TILE_AIR);
+        self.particles.clear();
+        self.wake_all();
     }
 }
 
@@ -0,0 +1,119 @@
+use super::*;
+use png::{BitDepth, ColorType, Decoder, DecodingError, Encoder, EncodingError, Transformations};
+use std::io::{Read, Write};
+
+/// Pixels whose alpha is below this value are imported as air.
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// How an image whose size doesn't match the grid is mapped onto it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ImportMode {
+    /// The image is anchored at the top left of the grid and anything outside of the grid is dropped.
+    #[default]
+    Crop,
+    /// The image is stretched to cover the whole grid using nearest neighbour sampling.
+    Scale,
+}
+
+impl Grid {
+    /// Writes this grid as a RGBA PNG image, each tile being drawn as a `scale` x `scale` square.
+    pub fn export_png<W: Write>(&self, writer: W, scale: u32) -> Result<(), EncodingError> {
+        let scale = scale.max(1) as usize;
+        let (width, height) = (self.size().0 as usize, self.size().1 as usize);
+        let row_len = width * scale * 4;
+        let mut data = vec![0u8; row_len * height * scale];
+
+        for (position, tile) in self.iter_tiles() {
+            let color = self.materials().color(tile.tile_type, tile.variant);
+            for ty in 0..scale {
+                let row = (position.y as usize * scale + ty) * row_len;
+                for tx in 0..scale {
+                    let idx = row + (position.x as usize * scale + tx) * 4;
+                    data[idx..idx + 4].copy_from_slice(&color[..4]);
+                }
+            }
+        }
+
+        let mut encoder = Encoder::new(writer, (width * scale) as u32, (height * scale) as u32);
+        encoder.set_color(ColorType::RGBA);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&data)
+    }
+
+    /// Replaces the content of this grid by a PNG image, mapping each pixel to the tile with the nearest color.
+    pub fn import_png<R: Read>(
+        &mut self,
+        reader: R,
+        mode: ImportMode,
+    ) -> Result<(), DecodingError> {
+        let mut decoder = Decoder::new(reader);
+        decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+        let (info, mut reader) = decoder.read_info()?;
+
+        let mut data = vec![0u8; info.buffer_size()];
+        reader.next_frame(&mut data)?;
+
+        // the decoder expands indexed images so only gray, gray alpha, RGB and RGBA are left
+        let channels = info.color_type.samples();
+
+        let pixel_at = |x: u64, y: u64| -> [u8; 4] {
+            let idx = y as usize * info.line_size + x as usize * channels;
+            match channels {
+                1 => [data[idx], data[idx], data[idx], 255],
+                2 => [data[idx], data[idx], data[idx], data[idx + 1]],
+                3 => [data[idx], data[idx + 1], data[idx + 2], 255],
+                _ => [data[idx], data[idx + 1], data[idx + 2], data[idx + 3]],
+            }
+        };
+
+        let image_size = (info.width as u64, info.height as u64);
+        let size = self.size();
+
+        for x in 0..size.0 {
+            for y in 0..size.1 {
+                let source = match mode {
+                    ImportMode::Crop if x < image_size.0 && y < image_size.1 => Some((x, y)),
+                    ImportMode::Crop => None,
+                    ImportMode::Scale => {
+                        Some((x * image_size.0 / size.0, y * image_size.1 / size.1))
+                    }
+                };
+
+                let tile = source
+                    .map(|(sx, sy)| nearest_tile(self.materials(), pixel_at(sx, sy)))
+                    .unwrap_or(TILE_AIR);
+                self.set_tile((x as i64, y as i64).into(), tile);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the tile whose color is the closest to the given RGBA pixel.
+/// Only transparent pixels are mapped to air, whose color would otherwise be the closest to dark pixels.
+fn nearest_tile(materials: &MaterialRegistry, pixel: [u8; 4]) -> Tile {
+    if pixel[3] < ALPHA_THRESHOLD {
+        return TILE_AIR;
+    }
+
+    let distance = |color: &[u8; 4]| -> u32 {
+        (0..3)
+            .map(|c| (pixel[c] as i32 - color[c] as i32).pow(2) as u32)
+            .sum()
+    };
+
+    materials
+        .iter()
+        .filter(|(tile_type, _)| *tile_type != TileType::AIR)
+        .flat_map(|(tile_type, material)| {
+            material
+                .colors
+                .iter()
+                .enumerate()
+                .map(move |(variant, color)| ((tile_type, variant as u8), distance(color)))
+        })
+        .min_by_key(|(_, distance)| *distance)
+        .map(|((tile_type, variant), _)| materials.create_tile(tile_type, variant))
+        .unwrap_or(TILE_AIR)
+}
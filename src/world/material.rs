@@ -0,0 +1,466 @@
+use super::{Tile, TileType, Vec2f};
+use serde::Deserialize;
+use std::{
+    error, fmt, fs, io,
+    path::Path,
+    sync::{Arc, OnceLock},
+};
+
+/// The temperature tiles are created at unless their material says otherwise, and which
+/// dissipating materials drift back to.
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+
+/// The material definitions shipped with the game, used when no other file is provided.
+pub const BUILTIN_MATERIALS: &str = include_str!("../../assets/materials.ron");
+
+/// How a material moves through the grid.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum MaterialState {
+    /// Doesn't move on its own but can be affected by its surroundings.
+    Solid,
+    /// Falls and piles up.
+    Powder,
+    /// Falls and spreads horizontally.
+    Liquid,
+    /// Fills the space other materials move through.
+    Gas,
+    /// Never moves nor changes.
+    Static,
+}
+
+/// A material of the simulation as defined in a materials file.
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    /// The color of each tile variant.
+    pub colors: Vec<[u8; 4]>,
+    pub density: f32,
+    pub state: MaterialState,
+    /// The temperature new tiles of this material are created at.
+    pub temperature: f32,
+    /// How fast heat flows through this material, between 0 and 1.
+    pub conductivity: f32,
+    /// How fast tiles of this material drift back to the ambient temperature, between 0 and 1.
+    pub dissipation: f32,
+    /// The material this one turns into above the given temperature.
+    pub heats_into: Option<(f32, TileType)>,
+    /// The material this one turns into below the given temperature.
+    pub cools_into: Option<(f32, TileType)>,
+    /// The average number of steps a tile of this material lasts before decaying.
+    pub lifetime: Option<u32>,
+    /// What tiles of this material decay into once their lifetime is over.
+    pub decays_into: TileType,
+    /// The chance each step of a tile of this material catching fire from a burning neighbour.
+    pub flammability: f64,
+    /// The average number of steps a burning tile of this material lasts before burning out.
+    pub burn_duration: u32,
+    /// What burning tiles of this material turn into once burnt out.
+    pub burns_into: TileType,
+    /// The material burning tiles of this material release into the empty cells around them.
+    pub flame: Option<TileType>,
+    /// Whether tiles of this material set their flammable neighbours on fire.
+    pub ignites: bool,
+    /// The radius and power of the explosion burning tiles of this material cause once burnt out.
+    pub explosion: Option<(f32, f32)>,
+    /// The blast power needed to destroy tiles of this material.
+    pub blast_resistance: f32,
+    /// The reactions this material triggers with its neighbours, in order of priority.
+    /// These are the entries of the reaction table whose first material is this one.
+    pub reactions: Vec<Reaction>,
+}
+
+impl Material {
+    /// Returns the color of the given tile variant.
+    pub fn color(&self, variant: u8) -> &[u8; 4] {
+        &self.colors[variant as usize % self.colors.len()]
+    }
+
+    /// Returns whether tiles of this material can change without moving, by decaying, setting their
+    /// neighbours on fire or reacting with them.
+    pub fn is_restless(&self) -> bool {
+        self.lifetime.is_some() || self.ignites || !self.reactions.is_empty()
+    }
+
+    /// Returns the material a tile of this material turns into at the given temperature, if any.
+    pub fn phase_change(&self, temperature: f32) -> Option<TileType> {
+        match (self.heats_into, self.cools_into) {
+            (Some((threshold, tile_type)), _) if temperature > threshold => Some(tile_type),
+            (_, Some((threshold, tile_type))) if temperature < threshold => Some(tile_type),
+            _ => None,
+        }
+    }
+}
+
+/// An entry of the reaction table, turning a tile and one of its neighbours into other materials.
+#[derive(Clone, Debug)]
+pub struct Reaction {
+    /// The neighbour material triggering the reaction.
+    pub with: TileType,
+    /// The chance of the reaction happening each step a neighbour matches.
+    pub probability: f64,
+    /// What the reacting tile turns into.
+    pub becomes: TileType,
+    /// What the neighbour turns into.
+    pub other_becomes: TileType,
+    /// An extra tile spawned in an empty cell around the reacting tile.
+    pub byproduct: Option<TileType>,
+}
+
+/// An error occurring when building a material registry.
+#[derive(Debug)]
+pub enum RegistryError {
+    Io(io::Error),
+    Parse(ron::Error),
+    /// The file doesn't define any material.
+    Empty,
+    /// The file defines more materials than a tile can reference.
+    TooManyMaterials(usize),
+    /// A material has no color.
+    NoColor(String),
+    /// Two materials share the same name.
+    DuplicateMaterial(String),
+    /// A material name is referenced but not defined.
+    UnknownMaterial(String),
+    /// A reaction probability isn't between 0 and 1.
+    InvalidProbability(f64),
+    /// A material conductivity or dissipation isn't between 0 and 1.
+    InvalidRate(String),
+    /// A material flammability isn't between 0 and 1.
+    InvalidFlammability(String),
+    /// A material has a lifetime of zero steps.
+    InvalidLifetime(String),
+    /// A material has a burn duration of zero steps.
+    InvalidBurnDuration(String),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Io(err) => write!(f, "i/o error: {}", err),
+            RegistryError::Parse(err) => write!(f, "invalid materials file: {}", err),
+            RegistryError::Empty => write!(f, "no material is defined"),
+            RegistryError::TooManyMaterials(count) => {
+                write!(
+                    f,
+                    "{} materials are defined, at most 256 are allowed",
+                    count
+                )
+            }
+            RegistryError::NoColor(name) => write!(f, "material `{}` has no color", name),
+            RegistryError::DuplicateMaterial(name) => {
+                write!(f, "material `{}` is defined twice", name)
+            }
+            RegistryError::UnknownMaterial(name) => write!(f, "unknown material `{}`", name),
+            RegistryError::InvalidRate(name) => write!(
+                f,
+                "material `{}` conductivity and dissipation must be between 0 and 1",
+                name
+            ),
+            RegistryError::InvalidFlammability(name) => write!(
+                f,
+                "material `{}` flammability must be between 0 and 1",
+                name
+            ),
+            RegistryError::InvalidLifetime(name) => {
+                write!(f, "material `{}` lifetime must be at least one step", name)
+            }
+            RegistryError::InvalidBurnDuration(name) => write!(
+                f,
+                "material `{}` burn duration must be at least one step",
+                name
+            ),
+            RegistryError::InvalidProbability(probability) => write!(
+                f,
+                "reaction probability {} isn't between 0 and 1",
+                probability
+            ),
+        }
+    }
+}
+
+impl error::Error for RegistryError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RegistryError::Io(err) => Some(err),
+            RegistryError::Parse(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct MaterialsFile {
+    materials: Vec<MaterialDef>,
+    #[serde(default)]
+    reactions: Vec<ReactionDef>,
+}
+
+#[derive(Deserialize)]
+struct MaterialDef {
+    name: String,
+    colors: Vec<(u8, u8, u8, u8)>,
+    density: f32,
+    state: MaterialState,
+    #[serde(default = "default_temperature")]
+    temperature: f32,
+    #[serde(default = "default_conductivity")]
+    conductivity: f32,
+    #[serde(default)]
+    dissipation: f32,
+    #[serde(default)]
+    heats_into: Option<(f32, String)>,
+    #[serde(default)]
+    cools_into: Option<(f32, String)>,
+    #[serde(default)]
+    lifetime: Option<u32>,
+    #[serde(default)]
+    decays_into: Option<String>,
+    #[serde(default)]
+    flammability: f64,
+    #[serde(default = "default_burn_duration")]
+    burn_duration: u32,
+    #[serde(default)]
+    burns_into: Option<String>,
+    #[serde(default)]
+    flame: Option<String>,
+    #[serde(default)]
+    ignites: bool,
+    #[serde(default)]
+    explosion: Option<(f32, f32)>,
+    #[serde(default = "default_blast_resistance")]
+    blast_resistance: f32,
+}
+
+fn default_temperature() -> f32 {
+    AMBIENT_TEMPERATURE
+}
+
+fn default_conductivity() -> f32 {
+    0.1
+}
+
+fn default_burn_duration() -> u32 {
+    1
+}
+
+fn default_blast_resistance() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+struct ReactionDef {
+    material: String,
+    with: String,
+    #[serde(default = "default_probability")]
+    probability: f64,
+    becomes: String,
+    other_becomes: String,
+    #[serde(default)]
+    byproduct: Option<String>,
+}
+
+fn default_probability() -> f64 {
+    1.0
+}
+
+fn builtin_registry() -> &'static Arc<MaterialRegistry> {
+    static BUILTIN: OnceLock<Arc<MaterialRegistry>> = OnceLock::new();
+
+    BUILTIN.get_or_init(|| {
+        Arc::new(
+            MaterialRegistry::from_ron(BUILTIN_MATERIALS).expect("Builtin materials are invalid"),
+        )
+    })
+}
+
+/// Returns the RGBA color of a tile of the given type and variant among the builtin materials.
+#[deprecated(note = "tile types index a `MaterialRegistry`, use `MaterialRegistry::color` instead")]
+pub fn get_color(tile_type: TileType, variant: u8) -> &'static [u8] {
+    builtin_registry().color(tile_type, variant)
+}
+
+/// The set of materials a grid is simulated with.
+/// Tile types are indices into this registry, the first material being the empty tile.
+#[derive(Debug)]
+pub struct MaterialRegistry {
+    materials: Vec<Material>,
+}
+
+impl MaterialRegistry {
+    /// Returns the registry built from the builtin material definitions.
+    pub fn builtin() -> Arc<MaterialRegistry> {
+        builtin_registry().clone()
+    }
+
+    /// Builds a registry from a materials file.
+    pub fn load_file<P: AsRef<Path>>(path: P) -> Result<MaterialRegistry, RegistryError> {
+        let source = fs::read_to_string(path).map_err(RegistryError::Io)?;
+        MaterialRegistry::from_ron(&source)
+    }
+
+    /// Builds a registry from material definitions written in RON.
+    pub fn from_ron(source: &str) -> Result<MaterialRegistry, RegistryError> {
+        let file: MaterialsFile = ron::from_str(source).map_err(RegistryError::Parse)?;
+
+        if file.materials.is_empty() {
+            return Err(RegistryError::Empty);
+        }
+
+        if file.materials.len() > u8::MAX as usize + 1 {
+            return Err(RegistryError::TooManyMaterials(file.materials.len()));
+        }
+
+        for (idx, def) in file.materials.iter().enumerate() {
+            if def.colors.is_empty() {
+                return Err(RegistryError::NoColor(def.name.clone()));
+            }
+
+            if file.materials[..idx].iter().any(|m| m.name == def.name) {
+                return Err(RegistryError::DuplicateMaterial(def.name.clone()));
+            }
+
+            if !(0.0..=1.0).contains(&def.conductivity) || !(0.0..=1.0).contains(&def.dissipation) {
+                return Err(RegistryError::InvalidRate(def.name.clone()));
+            }
+
+            if !(0.0..=1.0).contains(&def.flammability) {
+                return Err(RegistryError::InvalidFlammability(def.name.clone()));
+            }
+
+            if def.lifetime == Some(0) {
+                return Err(RegistryError::InvalidLifetime(def.name.clone()));
+            }
+
+            if def.burn_duration == 0 {
+                return Err(RegistryError::InvalidBurnDuration(def.name.clone()));
+            }
+        }
+
+        let find = |name: &str| -> Result<TileType, RegistryError> {
+            file.materials
+                .iter()
+                .position(|def| def.name == name)
+                .map(|idx| TileType(idx as u8))
+                .ok_or_else(|| RegistryError::UnknownMaterial(name.to_string()))
+        };
+
+        let phase_change = |change: &Option<(f32, String)>| {
+            change
+                .as_ref()
+                .map(|(threshold, name)| Ok((*threshold, find(name)?)))
+                .transpose()
+        };
+
+        let mut materials = file
+            .materials
+            .iter()
+            .map(|def| {
+                Ok(Material {
+                    name: def.name.clone(),
+                    colors: def
+                        .colors
+                        .iter()
+                        .map(|&(r, g, b, a)| [r, g, b, a])
+                        .collect(),
+                    density: def.density,
+                    state: def.state,
+                    temperature: def.temperature,
+                    conductivity: def.conductivity,
+                    dissipation: def.dissipation,
+                    heats_into: phase_change(&def.heats_into)?,
+                    cools_into: phase_change(&def.cools_into)?,
+                    lifetime: def.lifetime,
+                    decays_into: def
+                        .decays_into
+                        .as_deref()
+                        .map(find)
+                        .transpose()?
+                        .unwrap_or(TileType::AIR),
+                    flammability: def.flammability,
+                    burn_duration: def.burn_duration,
+                    burns_into: def
+                        .burns_into
+                        .as_deref()
+                        .map(find)
+                        .transpose()?
+                        .unwrap_or(TileType::AIR),
+                    flame: def.flame.as_deref().map(find).transpose()?,
+                    ignites: def.ignites,
+                    explosion: def.explosion,
+                    blast_resistance: def.blast_resistance,
+                    reactions: Vec::new(),
+                })
+            })
+            .collect::<Result<Vec<_>, RegistryError>>()?;
+
+        for def in &file.reactions {
+            if !(0.0..=1.0).contains(&def.probability) {
+                return Err(RegistryError::InvalidProbability(def.probability));
+            }
+
+            let reaction = Reaction {
+                with: find(&def.with)?,
+                probability: def.probability,
+                becomes: find(&def.becomes)?,
+                other_becomes: find(&def.other_becomes)?,
+                byproduct: def.byproduct.as_deref().map(find).transpose()?,
+            };
+
+            materials[find(&def.material)?.0 as usize]
+                .reactions
+                .push(reaction);
+        }
+
+        Ok(MaterialRegistry { materials })
+    }
+
+    /// Returns the material of the given tile type.
+    /// Tile types which aren't part of this registry are treated as the empty tile.
+    pub fn get(&self, tile_type: TileType) -> &Material {
+        self.materials
+            .get(tile_type.0 as usize)
+            .unwrap_or(&self.materials[0])
+    }
+
+    /// Returns the tile type of the material with the given name.
+    pub fn find(&self, name: &str) -> Option<TileType> {
+        self.materials
+            .iter()
+            .position(|material| material.name == name)
+            .map(|idx| TileType(idx as u8))
+    }
+
+    /// Returns a new tile of the given type and variant at the initial temperature of its material.
+    pub fn create_tile(&self, tile_type: TileType, variant: u8) -> Tile {
+        Tile {
+            variant,
+            tile_type,
+            temperature: self.get(tile_type).temperature,
+            burning: false,
+            velocity: Vec2f::default(),
+        }
+    }
+
+    /// Returns the color of a tile of the given type and variant.
+    pub fn color(&self, tile_type: TileType, variant: u8) -> &[u8; 4] {
+        self.get(tile_type).color(variant)
+    }
+
+    /// Returns the number of materials in this registry.
+    pub fn len(&self) -> usize {
+        self.materials.len()
+    }
+
+    /// Returns whether this registry has no material, which never happens for a built registry.
+    pub fn is_empty(&self) -> bool {
+        self.materials.is_empty()
+    }
+
+    /// Returns an iterator over every material along with its tile type.
+    pub fn iter(&self) -> impl Iterator<Item = (TileType, &Material)> {
+        self.materials
+            .iter()
+            .enumerate()
+            .map(|(idx, material)| (TileType(idx as u8), material))
+    }
+}
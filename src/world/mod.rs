@@ -1,33 +1,44 @@
+mod chunk;
 mod grid;
+mod image;
+mod material;
+mod parallel;
+mod particles;
+mod save;
+mod streaming;
 mod tiles;
 
-use core::ops::Add;
-use strum::{EnumIter, IntoStaticStr};
+use core::ops::{Add, Sub};
+use rand_pcg::Pcg32;
 
+pub use chunk::*;
 pub use grid::*;
-pub use tiles::*;
-
-#[derive(Clone, Copy, PartialEq, Debug, EnumIter, IntoStaticStr)]
-#[repr(u8)]
-pub enum TileType {
-    Air,
-    Sand,
-    Dirt,
-    Stone,
-    Water,
-    Lava,
-    Wall,
-    Acid,
-}
-
-impl Default for TileType {
-    fn default() -> Self {
-        TileType::Sand
-    }
+pub use image::*;
+pub use material::*;
+use parallel::WindowPool;
+pub use particles::*;
+pub use save::*;
+pub use streaming::*;
+use tiles::*;
+
+/// The random number generator driving the simulation.
+/// PCG32 is used as its output is fully specified and thus identical across platforms.
+pub type SimRng = Pcg32;
+
+/// The material of a tile in the simulation grid, as an index into the [`MaterialRegistry`] of the grid.
+///
+/// This used to be an enum of the hardcoded materials. Their tile types are now looked up by name,
+/// `TileType::Sand` becoming `registry.find("Sand")`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct TileType(pub u8);
+
+impl TileType {
+    /// The empty tile, which is always the first material of a registry.
+    pub const AIR: TileType = TileType(0);
 }
 
 /// A struct representing coordinates of a tile in the simulation grid.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Pos2i {
     pub x: i64,
     pub y: i64,
@@ -57,3 +68,84 @@ impl Add<Pos2i> for Pos2i {
         }
     }
 }
+
+impl Sub<Pos2i> for Pos2i {
+    type Output = Pos2i;
+    fn sub(self, rhs: Pos2i) -> Self::Output {
+        Pos2i {
+            x: self.x - rhs.x,
+            y: self.y - rhs.y,
+        }
+    }
+}
+
+impl Pos2i {
+    /// Returns the positions of the line going from this position to the given one, both included,
+    /// as traced by Bresenham's algorithm.
+    pub fn line_to(self, to: Pos2i) -> Line {
+        let delta = ((to.x - self.x).abs(), -(to.y - self.y).abs());
+
+        Line {
+            current: self,
+            end: to,
+            delta,
+            step: ((to.x - self.x).signum(), (to.y - self.y).signum()),
+            error: delta.0 + delta.1,
+            done: false,
+        }
+    }
+}
+
+/// An iterator over the positions of a line, created with [`Pos2i::line_to`].
+pub struct Line {
+    current: Pos2i,
+    end: Pos2i,
+    delta: (i64, i64),
+    step: (i64, i64),
+    error: i64,
+    done: bool,
+}
+
+impl Iterator for Line {
+    type Item = Pos2i;
+
+    fn next(&mut self) -> Option<Pos2i> {
+        if self.done {
+            return None;
+        }
+
+        let position = self.current;
+
+        if position == self.end {
+            self.done = true;
+            return Some(position);
+        }
+
+        let error = 2 * self.error;
+
+        if error >= self.delta.1 {
+            self.error += self.delta.1;
+            self.current.x += self.step.0;
+        }
+
+        if error <= self.delta.0 {
+            self.error += self.delta.0;
+            self.current.y += self.step.1;
+        }
+
+        Some(position)
+    }
+}
+
+/// A vector of floating point coordinates, used for the velocity of tiles.
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Vec2f {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl From<(f32, f32)> for Vec2f {
+    fn from(vec: (f32, f32)) -> Self {
+        Vec2f { x: vec.0, y: vec.1 }
+    }
+}
@@ -0,0 +1,167 @@
+use super::*;
+use rayon::prelude::*;
+
+/// How far around its chunk the update of a tile may read and write other tiles, in cells: tiles move
+/// at most [`MAX_CELLS_PER_STEP`] cells and then touch the neighbours of the cell they end up in.
+/// Explosions reach further and are only set off once the parallel phases are over.
+const WINDOW_MARGIN: i64 = MAX_CELLS_PER_STEP + 2;
+
+// chunks simulated during the same phase are a whole chunk apart so their windows never overlap,
+// which keeps tiles from being moved by two threads at once
+const _: () = assert!(2 * WINDOW_MARGIN <= CHUNK_SIZE);
+
+/// The chunks simulated during each phase of a parallel step, by parity of their column and row.
+const PHASES: [(usize, usize); 4] = [(0, 0), (1, 0), (0, 1), (1, 1)];
+
+/// A copy of the tiles around a chunk, simulated on its own during a phase of a parallel step.
+struct Window {
+    /// The position of the top left tile of the window in the grid.
+    origin: Pos2i,
+    grid: Grid,
+}
+
+/// One window per thread of the pool running parallel steps, reused from a step to the other.
+#[derive(Default)]
+pub(super) struct WindowPool(Vec<Window>);
+
+impl Clone for WindowPool {
+    /// Windows only hold scratch data, so copies of a grid start with an empty pool.
+    fn clone(&self) -> WindowPool {
+        WindowPool::default()
+    }
+}
+
+impl Grid {
+    /// Simulates the dirty regions of awake chunks, four phases of chunks which can't reach each other
+    /// being updated in parallel, as many at a time as there are threads.
+    /// The random number generator of each chunk is seeded from the given seed and the index of the chunk.
+    pub(super) fn update_chunks_parallel(&mut self, seed: u64) {
+        let left_to_right = self.tick % 2 == 0;
+        let per_row = self.chunks_per_row();
+        let mut pool = std::mem::take(&mut self.windows).0;
+
+        let threads = rayon::current_num_threads().max(1);
+        while pool.len() < threads {
+            pool.push(Window {
+                origin: (0, 0).into(),
+                grid: Grid::with_materials((0, 0), self.seed(), self.materials().clone()),
+            });
+        }
+
+        for (column, row) in PHASES.iter().copied() {
+            let scheduled: Vec<usize> = (0..self.chunks.len())
+                .filter(|idx| idx % per_row % 2 == column && idx / per_row % 2 == row)
+                .filter(|&idx| self.chunks[idx].dirty.is_some())
+                .collect();
+
+            // windows don't overlap so merging them in any order gives the same grid
+            for batch in scheduled.chunks(threads) {
+                let windows = &mut pool[..batch.len()];
+
+                windows
+                    .par_iter_mut()
+                    .zip(batch)
+                    .for_each(|(window, &idx)| {
+                        self.simulate_window(window, idx, seed, left_to_right)
+                    });
+
+                for window in windows.iter() {
+                    self.merge_window(window);
+                }
+            }
+        }
+
+        self.windows = WindowPool(pool);
+    }
+
+    /// Simulates the dirty region of the given chunk on a copy of the tiles around it.
+    fn simulate_window(&self, window: &mut Window, chunk: usize, seed: u64, left_to_right: bool) {
+        let bounds = self.chunks[chunk].bounds;
+        let (width, height) = (self.size().0 as i64, self.size().1 as i64);
+
+        let origin: Pos2i = (
+            (bounds.min.x - WINDOW_MARGIN).max(0),
+            (bounds.min.y - WINDOW_MARGIN).max(0),
+        )
+            .into();
+        let end: Pos2i = (
+            (bounds.max.x + WINDOW_MARGIN).min(width - 1),
+            (bounds.max.y + WINDOW_MARGIN).min(height - 1),
+        )
+            .into();
+        let size = ((end.x - origin.x + 1) as u64, (end.y - origin.y + 1) as u64);
+
+        let grid = &mut window.grid;
+        grid.reset(size, self.tick);
+        window.origin = origin;
+
+        let row_length = size.0 as usize;
+
+        for y in 0..size.1 as i64 {
+            let from = self.index_of(origin + (0, y).into()).unwrap();
+            let to = y as usize * row_length;
+
+            grid.tiles[to..to + row_length].copy_from_slice(&self.tiles[from..from + row_length]);
+            grid.updated[to..to + row_length]
+                .copy_from_slice(&self.updated[from..from + row_length]);
+        }
+
+        let dirty = self.chunks[chunk].dirty.unwrap();
+        let rect = Rect {
+            min: dirty.min - origin,
+            max: dirty.max - origin,
+        };
+
+        let mut rng = SimRng::new(seed, chunk as u64);
+        let materials = self.materials().clone();
+        grid.update_rect(&mut rng, &materials, rect, left_to_right);
+    }
+
+    /// Writes the tiles of a simulated window back into the grid, along with the regions it woke up,
+    /// the particles it launched and the explosions it caused.
+    fn merge_window(&mut self, window: &Window) {
+        let Window { origin, grid } = window;
+        let origin = *origin;
+
+        for (idx, tile) in grid.tiles.iter().enumerate() {
+            let local: Pos2i = (
+                (idx % grid.size().0 as usize) as i64,
+                (idx / grid.size().0 as usize) as i64,
+            )
+                .into();
+            let position = origin + local;
+            let global = self.index_of(position).unwrap();
+
+            self.updated[global] |= grid.updated[idx];
+
+            if self.tiles[global] != *tile {
+                self.tiles[global] = *tile;
+                self.mark_changed(position);
+            }
+        }
+
+        for chunk in &grid.chunks {
+            if let Some(rect) = chunk.next_dirty {
+                self.wake_rect(Rect {
+                    min: rect.min + origin,
+                    max: rect.max + origin,
+                });
+            }
+        }
+
+        for particle in &grid.particles {
+            self.spawn_particle(Particle {
+                position: (
+                    particle.position.x + origin.x as f32,
+                    particle.position.y + origin.y as f32,
+                )
+                    .into(),
+                ..*particle
+            });
+        }
+
+        for (center, radius, power) in &grid.explosions {
+            self.explosions.push((*center + origin, *radius, *power));
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use super::*;
+
+/// A tile flying freely over the grid, not bound to its cells.
+/// Particles move along their velocity under gravity and settle back into the grid as a tile
+/// once they hit a cell which isn't air.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Particle {
+    /// The position of the particle, in cells.
+    pub position: Vec2f,
+    /// The speed of the particle in cells per step, y pointing down.
+    pub velocity: Vec2f,
+    /// The tile the particle settles as.
+    pub tile: Tile,
+}
+
+impl Particle {
+    /// Creates a particle flying from the center of the given cell.
+    pub fn new(cell: Pos2i, velocity: Vec2f, tile: Tile) -> Particle {
+        Particle {
+            position: (cell.x as f32 + 0.5, cell.y as f32 + 0.5).into(),
+            velocity,
+            tile,
+        }
+    }
+
+    /// Returns the cell the particle is flying over.
+    pub fn cell(&self) -> Pos2i {
+        (
+            self.position.x.floor() as i64,
+            self.position.y.floor() as i64,
+        )
+            .into()
+    }
+}
+
+impl Grid {
+    /// Returns the particles flying over the grid.
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Adds a particle flying over the grid.
+    pub fn spawn_particle(&mut self, particle: Particle) {
+        self.particles.push(particle);
+    }
+
+    /// Turns the tile at the given position into a particle flying at the given velocity, leaving air in its place.
+    pub fn launch_tile(&mut self, position: Pos2i, velocity: Vec2f) {
+        if let Some(tile) = self.get_tile(position) {
+            self.set_tile(position, TILE_AIR);
+            self.spawn_particle(Particle::new(position, velocity, tile));
+        }
+    }
+
+    /// Moves every particle, settling the ones which landed back into the grid.
+    pub(crate) fn update_particles(&mut self) {
+        let mut particles = std::mem::take(&mut self.particles);
+        particles.retain_mut(|particle| self.move_particle(particle));
+        self.particles = particles;
+    }
+
+    /// Moves a particle along its velocity until it hits a cell which isn't air.
+    /// Returns whether the particle is still flying.
+    fn move_particle(&mut self, particle: &mut Particle) -> bool {
+        particle.velocity.y = (particle.velocity.y + GRAVITY).min(MAX_SPEED);
+
+        let from = particle.cell();
+        particle.position.x += particle.velocity.x;
+        particle.position.y += particle.velocity.y;
+
+        let columns = 0..self.size().0 as i64;
+        let mut last = from;
+
+        for cell in from.line_to(particle.cell()).skip(1) {
+            match self.get_tile(cell) {
+                Some(tile) if tile.tile_type == TileType::AIR => last = cell,
+                // particles can fly above the grid but bump into its sides and bottom
+                None if cell.y < 0 && columns.contains(&cell.x) => last = cell,
+                // particles bumping into the sides above the grid slide down along them
+                None if last.y < 0 && columns.contains(&last.x) => {
+                    particle.position.x = last.x as f32 + 0.5;
+                    particle.position.y = last.y as f32 + 0.5;
+                    particle.velocity.x = 0.0;
+                    return true;
+                }
+                _ => {
+                    if self.settle(last, particle) {
+                        return false;
+                    }
+
+                    // the grid is full, the particle waits above the grid for some room
+                    particle.position.x = last.x.clamp(columns.start, columns.end - 1) as f32 + 0.5;
+                    particle.position.y = -0.5;
+                    particle.velocity = Vec2f::default();
+                    return true;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Places the tile of a landing particle at the given cell or, if it is taken, on the first air cell above it.
+    /// Particles landing on a full column settle on the nearest air cell of the grid instead.
+    /// Returns whether the tile was placed, which only fails when the grid is full.
+    fn settle(&mut self, cell: Pos2i, particle: &Particle) -> bool {
+        let tile = Tile {
+            velocity: particle.velocity,
+            ..particle.tile
+        };
+
+        let free = self.air_above(cell).or_else(|| self.nearest_air(cell));
+
+        match free {
+            Some(free) => {
+                self.set_tile(free, tile);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the first air cell of the grid at or above the given cell, in its column.
+    fn air_above(&self, cell: Pos2i) -> Option<Pos2i> {
+        let mut cell = cell;
+
+        while let Some(other) = self.get_tile(cell) {
+            if other.tile_type == TileType::AIR {
+                return Some(cell);
+            }
+
+            cell = cell + (0, -1).into();
+        }
+
+        None
+    }
+
+    /// Returns the air cell of the grid the closest to the given cell, searching rings of growing size around it.
+    fn nearest_air(&self, cell: Pos2i) -> Option<Pos2i> {
+        let size = (self.size().0 as i64, self.size().1 as i64);
+        if size.0 == 0 || size.1 == 0 {
+            return None;
+        }
+
+        let center: Pos2i = (cell.x.clamp(0, size.0 - 1), cell.y.clamp(0, size.1 - 1)).into();
+        let is_air = |cell: &Pos2i| {
+            self.get_tile(*cell)
+                .is_some_and(|tile| tile.tile_type == TileType::AIR)
+        };
+
+        (0..size.0.max(size.1)).find_map(|radius| {
+            let ring = (-radius..=radius)
+                .flat_map(move |d| [(d, -radius), (d, radius), (-radius, d), (radius, d)]);
+
+            ring.map(|offset| center + offset.into()).find(is_air)
+        })
+    }
+}
@@ -0,0 +1,329 @@
+use super::*;
+use std::{
+    error, fmt,
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+/// Magic bytes found at the start of every saved world.
+pub const WORLD_MAGIC: [u8; 4] = *b"PSSR";
+
+/// The current version of the world file format.
+///
+/// Layout (all numbers little endian):
+/// - magic bytes (4 bytes)
+/// - format version (u16)
+/// - grid width and height (2 x u64)
+/// - simulation seed (u64)
+/// - material table: a material count (u16) followed by each material name as a length (u16)
+///   and UTF-8 bytes. Tile type ids of the file are indices into this table.
+/// - three sections of run-length encoded values, each one filling the grid with runs made of a length (u32)
+///   followed by the value:
+///   - the tiles, as a tile type id (u8), a variant (u8) and flags (u8), bit 0 being set for burning tiles
+///   - their temperature (f32)
+///   - their velocity (2 x f32)
+///
+/// Temperatures and velocities are stored exactly, so that a loaded world simulates the same way as the one
+/// which was saved, but apart from the tiles so settled regions still compress into long runs.
+/// Particles flying over the grid aren't saved.
+pub const WORLD_FORMAT_VERSION: u16 = 1;
+
+/// The largest number of tiles a loaded world may have, so a corrupted header can't make loading
+/// allocate more memory than the machine has.
+pub const MAX_WORLD_TILES: u64 = 1 << 26;
+
+/// An error occurring when loading a world file.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    /// The data doesn't start with the expected magic bytes.
+    BadMagic,
+    /// The file was written with a format version this build doesn't know.
+    UnsupportedVersion(u16),
+    /// A tile run references a tile type id which doesn't exist.
+    UnknownTile(u8),
+    /// The world uses a material which isn't part of the registry it is loaded with.
+    UnknownMaterial(String),
+    /// A material name isn't valid UTF-8.
+    InvalidMaterialName,
+    /// The data ended before the grid was filled.
+    Truncated,
+    /// The tile runs don't add up to the grid size declared in the header.
+    RunOverflow,
+    /// The header declares a world with more than [`MAX_WORLD_TILES`] tiles.
+    TooLarge((u64, u64)),
+    /// The world doesn't have the size of the grid it is loaded into.
+    SizeMismatch {
+        expected: (u64, u64),
+        found: (u64, u64),
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io(err) => write!(f, "i/o error: {}", err),
+            LoadError::BadMagic => write!(f, "not a poussière world file"),
+            LoadError::UnsupportedVersion(version) => {
+                write!(f, "unsupported world format version {}", version)
+            }
+            LoadError::UnknownTile(id) => write!(f, "unknown tile id {}", id),
+            LoadError::UnknownMaterial(name) => write!(f, "unknown material `{}`", name),
+            LoadError::InvalidMaterialName => write!(f, "material name isn't valid UTF-8"),
+            LoadError::Truncated => write!(f, "world data is truncated"),
+            LoadError::RunOverflow => write!(f, "tile runs overflow the world size"),
+            LoadError::TooLarge(size) => write!(f, "world is too large: {}x{}", size.0, size.1),
+            LoadError::SizeMismatch { expected, found } => write!(
+                f,
+                "world size mismatch: expected {}x{}, found {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+        }
+    }
+}
+
+impl error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            LoadError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        if err.kind() == io::ErrorKind::UnexpectedEof {
+            LoadError::Truncated
+        } else {
+            LoadError::Io(err)
+        }
+    }
+}
+
+impl Grid {
+    /// Writes this grid to the given writer using the versioned world format.
+    pub fn save<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&WORLD_MAGIC)?;
+        writer.write_all(&WORLD_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.size().0.to_le_bytes())?;
+        writer.write_all(&self.size().1.to_le_bytes())?;
+        writer.write_all(&self.seed().to_le_bytes())?;
+
+        let materials = self.materials();
+        writer.write_all(&(materials.len() as u16).to_le_bytes())?;
+        for (_, material) in materials.iter() {
+            writer.write_all(&(material.name.len() as u16).to_le_bytes())?;
+            writer.write_all(material.name.as_bytes())?;
+        }
+
+        let tiles = || self.iter_tiles().map(|(_, tile)| tile);
+
+        write_runs(
+            &mut writer,
+            tiles().map(|tile| [tile.tile_type.0, tile.variant, tile.burning as u8]),
+            |writer, bytes| writer.write_all(&bytes),
+        )?;
+        write_runs(
+            &mut writer,
+            tiles().map(|tile| tile.temperature.to_bits()),
+            |writer, temperature| writer.write_all(&temperature.to_le_bytes()),
+        )?;
+        write_runs(
+            &mut writer,
+            tiles().map(|tile| (tile.velocity.x.to_bits(), tile.velocity.y.to_bits())),
+            |writer, (x, y)| {
+                writer.write_all(&x.to_le_bytes())?;
+                writer.write_all(&y.to_le_bytes())
+            },
+        )?;
+
+        writer.flush()
+    }
+
+    /// Reads a grid previously written with [`Grid::save`] using the builtin materials.
+    pub fn load<R: Read>(reader: R) -> Result<Grid, LoadError> {
+        Grid::load_with_materials(reader, MaterialRegistry::builtin())
+    }
+
+    /// Reads a grid previously written with [`Grid::save`], mapping its materials by name to the given registry.
+    pub fn load_with_materials<R: Read>(
+        mut reader: R,
+        materials: Arc<MaterialRegistry>,
+    ) -> Result<Grid, LoadError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != WORLD_MAGIC {
+            return Err(LoadError::BadMagic);
+        }
+
+        let version = read_u16(&mut reader)?;
+        if version != WORLD_FORMAT_VERSION {
+            return Err(LoadError::UnsupportedVersion(version));
+        }
+
+        let size = (read_u64(&mut reader)?, read_u64(&mut reader)?);
+        if size
+            .0
+            .checked_mul(size.1)
+            .map_or(true, |count| count > MAX_WORLD_TILES)
+        {
+            return Err(LoadError::TooLarge(size));
+        }
+
+        let seed = read_u64(&mut reader)?;
+
+        let names = read_material_table(&mut reader)?;
+        let tiles = read_sections(&mut reader, size, &names, &materials)?;
+
+        let mut grid = Grid::with_materials(size, seed, materials);
+        grid.restore(tiles);
+        Ok(grid)
+    }
+
+    /// Replaces the content of this grid by the world read from the given reader.
+    /// The world must have the same size as this grid.
+    pub fn load_into<R: Read>(&mut self, reader: R) -> Result<(), LoadError> {
+        let world = Grid::load_with_materials(reader, self.materials().clone())?;
+
+        if world.size() != self.size() {
+            return Err(LoadError::SizeMismatch {
+                expected: self.size(),
+                found: world.size(),
+            });
+        }
+
+        *self = world;
+        Ok(())
+    }
+}
+
+fn read_material_table<R: Read>(reader: &mut R) -> Result<Vec<String>, LoadError> {
+    let count = read_u16(reader)?;
+
+    (0..count)
+        .map(|_| {
+            let mut name = vec![0u8; read_u16(reader)? as usize];
+            reader.read_exact(&mut name)?;
+            String::from_utf8(name).map_err(|_| LoadError::InvalidMaterialName)
+        })
+        .collect()
+}
+
+/// Writes the given values as runs of equal values, each run being its length followed by the value.
+fn write_runs<W: Write, T: PartialEq>(
+    writer: &mut W,
+    values: impl Iterator<Item = T>,
+    write_value: impl Fn(&mut W, T) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut values = values.peekable();
+
+    while let Some(value) = values.next() {
+        let mut run_length = 1u32;
+        while run_length < u32::MAX && values.peek() == Some(&value) {
+            values.next();
+            run_length += 1;
+        }
+
+        writer.write_all(&run_length.to_le_bytes())?;
+        write_value(writer, value)?;
+    }
+
+    Ok(())
+}
+
+/// Reads runs written by [`write_runs`] until the given number of values is read.
+///
+/// The values only grow as runs are read, so that a header claiming a huge world doesn't make loading
+/// allocate memory for data the file doesn't have.
+fn read_runs<R: Read, T: Clone>(
+    reader: &mut R,
+    count: u64,
+    read_value: impl Fn(&mut R) -> Result<T, LoadError>,
+) -> Result<Vec<T>, LoadError> {
+    let mut values = Vec::new();
+
+    while (values.len() as u64) < count {
+        let run_length = read_u32(reader)? as u64;
+        let value = read_value(reader)?;
+
+        if values.len() as u64 + run_length > count {
+            return Err(LoadError::RunOverflow);
+        }
+
+        values.extend(std::iter::repeat(value).take(run_length as usize));
+    }
+
+    Ok(values)
+}
+
+/// Returns the tile type of the given id of the material table of a world file.
+fn resolve_tile_type(
+    id: u8,
+    names: &[String],
+    materials: &MaterialRegistry,
+) -> Result<TileType, LoadError> {
+    // materials of the table are only resolved when used so unused ones don't have to exist
+    let name = names.get(id as usize).ok_or(LoadError::UnknownTile(id))?;
+
+    materials
+        .find(name)
+        .ok_or_else(|| LoadError::UnknownMaterial(name.clone()))
+}
+
+fn read_sections<R: Read>(
+    reader: &mut R,
+    size: (u64, u64),
+    names: &[String],
+    materials: &MaterialRegistry,
+) -> Result<Vec<Tile>, LoadError> {
+    let tile_count = size.0 * size.1;
+
+    let mut tiles = read_runs(reader, tile_count, |reader| {
+        let mut data = [0u8; 3];
+        reader.read_exact(&mut data)?;
+
+        let mut tile =
+            materials.create_tile(resolve_tile_type(data[0], names, materials)?, data[1]);
+        tile.burning = data[2] & 1 != 0;
+        Ok(tile)
+    })?;
+
+    let temperatures = read_runs(reader, tile_count, |reader| {
+        read_f32(reader).map_err(LoadError::from)
+    })?;
+    let velocities = read_runs(reader, tile_count, |reader| {
+        Ok((read_f32(reader)?, read_f32(reader)?))
+    })?;
+
+    for ((tile, temperature), velocity) in tiles.iter_mut().zip(temperatures).zip(velocities) {
+        tile.temperature = temperature;
+        tile.velocity = velocity.into();
+    }
+
+    Ok(tiles)
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut bytes = [0u8; 2];
+    reader.read_exact(&mut bytes)?;
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32<R: Read>(reader: &mut R) -> io::Result<f32> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(f32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
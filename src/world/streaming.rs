@@ -0,0 +1,574 @@
+use super::*;
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// The width of the ring of chunks loaded around the simulated area, in chunks.
+const BORDER_CHUNKS: i64 = 1;
+
+/// How many chunks outside of the loaded area are kept in memory by default.
+pub const DEFAULT_CACHED_CHUNKS: usize = 256;
+
+/// A chunk outside of the loaded area kept in memory.
+struct CachedChunk {
+    tiles: Vec<Tile>,
+    /// The access to the cache which last used this chunk.
+    last_used: u64,
+    /// Whether the tiles changed since they were read from or written to disk.
+    modified: bool,
+}
+
+/// An unbounded world whose chunks are streamed from and to a directory.
+///
+/// Only a chunk aligned area around a movable center is simulated, by a [`Grid`] following it around.
+/// That grid also loads a ring of chunks around the simulated area which are never simulated: tiles leaving
+/// the area move into it and stay there until the area reaches them again.
+/// Chunks leaving the loaded area are kept in a cache whose least recently used chunks are written to the
+/// directory as world files, and read back once they are needed again. Chunks which were never written are
+/// filled with air.
+pub struct StreamingWorld {
+    grid: Grid,
+    /// The position of the top left tile of the loaded area in the world.
+    origin: Pos2i,
+    directory: PathBuf,
+    /// Chunks outside of the loaded area, by chunk coordinates.
+    cache: HashMap<Pos2i, CachedChunk>,
+    /// The number of chunks the cache holds before writing the least recently used ones to disk.
+    cache_capacity: usize,
+    /// The number of accesses to the cache so far.
+    accesses: u64,
+}
+
+impl StreamingWorld {
+    /// Opens the world stored in the given directory, creating it if needed, and loads the
+    /// area of the given number of (horizontal, vertical) chunks around the origin of the world.
+    pub fn open<P: AsRef<Path>>(
+        directory: P,
+        area: (u64, u64),
+        seed: u64,
+        materials: Arc<MaterialRegistry>,
+    ) -> Result<StreamingWorld, LoadError> {
+        fs::create_dir_all(directory.as_ref())?;
+
+        let size = loaded_size(area);
+        let origin = area_origin(size, (0, 0).into());
+
+        let mut world = StreamingWorld {
+            grid: Grid::with_materials(size, seed, materials),
+            origin,
+            directory: directory.as_ref().to_path_buf(),
+            cache: HashMap::new(),
+            cache_capacity: DEFAULT_CACHED_CHUNKS,
+            accesses: 0,
+        };
+
+        let mut tiles = world.grid.snapshot();
+
+        for chunk in world.loaded_chunks(origin, size).positions() {
+            let chunk_tiles = world.load_chunk(chunk)?;
+            copy_chunk(&mut tiles, size, origin, chunk, &chunk_tiles);
+        }
+
+        world.grid.restore(tiles);
+        Ok(world)
+    }
+
+    /// Returns the grid holding the loaded area, whose positions are relative to [`StreamingWorld::origin`].
+    pub fn grid(&self) -> &Grid {
+        &self.grid
+    }
+
+    /// Returns the grid holding the loaded area, whose positions are relative to [`StreamingWorld::origin`].
+    pub fn grid_mut(&mut self) -> &mut Grid {
+        &mut self.grid
+    }
+
+    /// Returns the position in the world of the top left tile of the loaded area.
+    pub fn origin(&self) -> Pos2i {
+        self.origin
+    }
+
+    /// Returns the world positions of the loaded area, the simulated area and the ring of chunks around it.
+    pub fn loaded_area(&self) -> Rect {
+        let size = self.grid.size();
+
+        Rect {
+            min: self.origin,
+            max: self.origin + (size.0 as i64 - 1, size.1 as i64 - 1).into(),
+        }
+    }
+
+    /// Returns the world positions of the simulated area.
+    pub fn simulated_area(&self) -> Rect {
+        let border = BORDER_CHUNKS * CHUNK_SIZE;
+        let loaded = self.loaded_area();
+
+        Rect {
+            min: loaded.min + (border, border).into(),
+            max: loaded.max - (border, border).into(),
+        }
+    }
+
+    /// Changes the number of chunks kept in memory outside of the loaded area, writing the least recently
+    /// used ones to disk if there are more.
+    pub fn set_cache_capacity(&mut self, chunks: usize) -> io::Result<()> {
+        self.cache_capacity = chunks.max(1);
+        self.evict(None)
+    }
+
+    /// Advances the simulated area by one tick, the ring of chunks around it staying as it is.
+    pub fn step(&mut self) {
+        let simulated = self.simulated_area();
+        let simulated = Rect {
+            min: simulated.min - self.origin,
+            max: simulated.max - self.origin,
+        };
+
+        for chunk in &mut self.grid.chunks {
+            if !simulated.contains(chunk.bounds.min) {
+                chunk.next_dirty = None;
+            }
+        }
+
+        self.grid.step();
+    }
+
+    /// Returns the tile at the given world position.
+    /// Chunks which are neither loaded nor cached are read from disk without being cached.
+    pub fn get_tile(&self, position: Pos2i) -> Result<Tile, LoadError> {
+        if let Some(tile) = self.grid.get_tile(position - self.origin) {
+            return Ok(tile);
+        }
+
+        let (chunk, idx) = split_position(position);
+
+        match self.cache.get(&chunk) {
+            Some(cached) => Ok(cached.tiles[idx]),
+            None => Ok(self.load_chunk(chunk)?[idx]),
+        }
+    }
+
+    /// Sets the tile at the given world position, caching its chunk if it isn't loaded.
+    /// Changes to chunks which aren't loaded are written to disk once they leave the cache or by the next
+    /// [`StreamingWorld::flush`].
+    pub fn set_tile(&mut self, position: Pos2i, tile: Tile) -> Result<(), LoadError> {
+        if self.grid.get_tile(position - self.origin).is_some() {
+            self.grid.set_tile(position - self.origin, tile);
+            return Ok(());
+        }
+
+        let (chunk, idx) = split_position(position);
+        let cached = self.cached_chunk(chunk)?;
+        cached.tiles[idx] = tile;
+        cached.modified = true;
+        Ok(())
+    }
+
+    /// Moves the simulated area so that it is centered on the given world position.
+    /// Chunks leaving the loaded area are cached while the ones entering it are read back.
+    pub fn set_center(&mut self, center: Pos2i) -> Result<(), LoadError> {
+        let size = self.grid.size();
+        self.relocate(area_origin(size, center), size)
+    }
+
+    /// Changes the number of (horizontal, vertical) chunks of the simulated area, keeping its top left corner
+    /// or its center in place.
+    pub fn set_area(&mut self, area: (u64, u64), anchor: ResizeAnchor) -> Result<(), LoadError> {
+        let size = loaded_size(area);
+
+        let origin = match anchor {
+            ResizeAnchor::TopLeft => self.origin,
+            ResizeAnchor::Center => {
+                let loaded = self.loaded_area();
+                let center: Pos2i = (
+                    (loaded.min.x + loaded.max.x + 1) / 2,
+                    (loaded.min.y + loaded.max.y + 1) / 2,
+                )
+                    .into();
+
+                area_origin(size, center)
+            }
+        };
+
+        self.relocate(origin, size)
+    }
+
+    /// Copies the tiles of the given world rectangle into a new grid.
+    pub fn region(&self, rect: Rect) -> Result<Grid, LoadError> {
+        let size = (
+            (rect.max.x - rect.min.x + 1) as u64,
+            (rect.max.y - rect.min.y + 1) as u64,
+        );
+
+        if size.0.saturating_mul(size.1) > MAX_WORLD_TILES {
+            return Err(LoadError::TooLarge(size));
+        }
+
+        let mut tiles = vec![TILE_AIR; (size.0 * size.1) as usize];
+        let chunks = Rect {
+            min: chunk_of(rect.min),
+            max: chunk_of(rect.max),
+        };
+
+        for chunk in chunks.positions() {
+            let chunk_tiles = self.chunk_tiles(chunk)?;
+
+            for (position, tile) in chunk_bounds(chunk).positions().zip(chunk_tiles) {
+                if rect.contains(position) {
+                    let local = position - rect.min;
+                    tiles[(local.y * size.0 as i64 + local.x) as usize] = tile;
+                }
+            }
+        }
+
+        let mut grid = Grid::with_materials(size, self.grid.seed(), self.grid.materials().clone());
+        grid.restore(tiles);
+        Ok(grid)
+    }
+
+    /// Copies the tiles of a grid into the world, its top left tile going to the given world position.
+    pub fn paste(&mut self, grid: &Grid, at: Pos2i) -> Result<(), LoadError> {
+        for (position, tile) in grid.iter_tiles() {
+            self.set_tile(at + position, *tile)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the smallest rectangle containing every tile of the world which isn't air, if any.
+    pub fn content_bounds(&self) -> Result<Option<Rect>, LoadError> {
+        let loaded = self.loaded_chunks(self.origin, self.grid.size());
+        let mut chunks: HashSet<Pos2i> = self.stored_chunks()?.into_iter().collect();
+        chunks.extend(self.cache.keys());
+        chunks.extend(loaded.positions());
+
+        let mut bounds: Option<Rect> = None;
+
+        for chunk in chunks {
+            let tiles = self.chunk_tiles(chunk)?;
+
+            for (position, tile) in chunk_bounds(chunk).positions().zip(tiles) {
+                if tile.tile_type != TileType::AIR {
+                    bounds = Some(match bounds {
+                        Some(bounds) => bounds.including(position),
+                        None => Rect {
+                            min: position,
+                            max: position,
+                        },
+                    });
+                }
+            }
+        }
+
+        Ok(bounds)
+    }
+
+    /// Writes every modified chunk of the world to disk, loaded or cached.
+    pub fn flush(&mut self) -> io::Result<()> {
+        for (chunk, cached) in &mut self.cache {
+            if cached.modified {
+                save_chunk(&self.directory, &self.grid, *chunk, &cached.tiles)?;
+                cached.modified = false;
+            }
+        }
+
+        for chunk in self
+            .loaded_chunks(self.origin, self.grid.size())
+            .positions()
+        {
+            let tiles = self.extract_chunk(chunk);
+            save_chunk(&self.directory, &self.grid, chunk, &tiles)?;
+        }
+
+        Ok(())
+    }
+
+    /// Moves the loaded area so that its top left tile is at the given world position, changing its size.
+    /// Particles flying out of the new area land where they are, if there is air there.
+    fn relocate(&mut self, origin: Pos2i, size: (u64, u64)) -> Result<(), LoadError> {
+        if origin == self.origin && size == self.grid.size() {
+            return Ok(());
+        }
+
+        let old = self.loaded_chunks(self.origin, self.grid.size());
+        let new = self.loaded_chunks(origin, size);
+
+        let mut tiles = vec![TILE_AIR; (size.0 * size.1) as usize];
+
+        for chunk in new.positions() {
+            let chunk_tiles = if old.contains(chunk) {
+                self.extract_chunk(chunk)
+            } else {
+                match self.cache.remove(&chunk) {
+                    Some(cached) => cached.tiles,
+                    None => self.load_chunk(chunk)?,
+                }
+            };
+
+            copy_chunk(&mut tiles, size, origin, chunk, &chunk_tiles);
+        }
+
+        for chunk in old.positions().filter(|chunk| !new.contains(*chunk)) {
+            let tiles = self.extract_chunk(chunk);
+            self.insert_cached(chunk, tiles, true)?;
+        }
+
+        let particles: Vec<Particle> = self.grid.particles().to_vec();
+        let shift = self.origin - origin;
+
+        if size != self.grid.size() {
+            self.grid.resize(size, ResizeAnchor::TopLeft);
+        }
+
+        self.grid.restore(tiles);
+        self.origin = origin;
+
+        for particle in particles {
+            let particle = Particle {
+                position: (
+                    particle.position.x + shift.x as f32,
+                    particle.position.y + shift.y as f32,
+                )
+                    .into(),
+                ..particle
+            };
+            let cell = particle.cell();
+
+            if (0..size.0 as i64).contains(&cell.x) && cell.y < size.1 as i64 {
+                self.grid.spawn_particle(particle);
+            } else if self.get_tile(cell + origin)?.tile_type == TileType::AIR {
+                self.set_tile(cell + origin, particle.tile)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the coordinates of the chunks of the loaded area of the given size whose top left tile
+    /// is at the given position.
+    fn loaded_chunks(&self, origin: Pos2i, size: (u64, u64)) -> Rect {
+        let min = chunk_of(origin);
+
+        Rect {
+            min,
+            max: min
+                + (
+                    size.0 as i64 / CHUNK_SIZE - 1,
+                    size.1 as i64 / CHUNK_SIZE - 1,
+                )
+                    .into(),
+        }
+    }
+
+    /// Returns a copy of the tiles of a chunk, wherever it is.
+    fn chunk_tiles(&self, chunk: Pos2i) -> Result<Vec<Tile>, LoadError> {
+        if self
+            .loaded_chunks(self.origin, self.grid.size())
+            .contains(chunk)
+        {
+            return Ok(self.extract_chunk(chunk));
+        }
+
+        match self.cache.get(&chunk) {
+            Some(cached) => Ok(cached.tiles.clone()),
+            None => self.load_chunk(chunk),
+        }
+    }
+
+    /// Returns a chunk outside of the loaded area, reading it from disk if it isn't cached.
+    fn cached_chunk(&mut self, chunk: Pos2i) -> Result<&mut CachedChunk, LoadError> {
+        if !self.cache.contains_key(&chunk) {
+            let tiles = self.load_chunk(chunk)?;
+            self.insert_cached(chunk, tiles, false)?;
+        }
+
+        self.accesses += 1;
+        let cached = self.cache.get_mut(&chunk).unwrap();
+        cached.last_used = self.accesses;
+        Ok(cached)
+    }
+
+    /// Caches the tiles of a chunk, making room for them if the cache is full.
+    fn insert_cached(&mut self, chunk: Pos2i, tiles: Vec<Tile>, modified: bool) -> io::Result<()> {
+        self.accesses += 1;
+        self.cache.insert(
+            chunk,
+            CachedChunk {
+                tiles,
+                last_used: self.accesses,
+                modified,
+            },
+        );
+
+        self.evict(Some(chunk))
+    }
+
+    /// Writes the least recently used chunks other than the given one to disk until the cache isn't
+    /// over capacity anymore.
+    fn evict(&mut self, keep: Option<Pos2i>) -> io::Result<()> {
+        while self.cache.len() > self.cache_capacity {
+            let oldest = self
+                .cache
+                .iter()
+                .filter(|(chunk, _)| Some(**chunk) != keep)
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(chunk, _)| *chunk);
+
+            let chunk = match oldest {
+                Some(chunk) => chunk,
+                None => break,
+            };
+
+            let cached = self.cache.remove(&chunk).unwrap();
+            if cached.modified {
+                save_chunk(&self.directory, &self.grid, chunk, &cached.tiles)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies the tiles of a loaded chunk, row by row.
+    fn extract_chunk(&self, chunk: Pos2i) -> Vec<Tile> {
+        chunk_bounds(chunk)
+            .positions()
+            .map(|position| self.grid.get_tile(position - self.origin).unwrap())
+            .collect()
+    }
+
+    /// Returns the coordinates of the chunks written to disk.
+    fn stored_chunks(&self) -> io::Result<Vec<Pos2i>> {
+        let mut chunks = Vec::new();
+
+        for entry in fs::read_dir(&self.directory)? {
+            let name = entry?.file_name();
+            let coordinates = name
+                .to_str()
+                .and_then(|name| name.strip_suffix(".bin"))
+                .and_then(|name| name.split_once('_'))
+                .and_then(|(x, y)| Some((x.parse::<i64>().ok()?, y.parse::<i64>().ok()?)));
+
+            if let Some(chunk) = coordinates {
+                chunks.push(chunk.into());
+            }
+        }
+
+        Ok(chunks)
+    }
+
+    /// Reads the tiles of a chunk from disk, chunks which were never saved being filled with air.
+    fn load_chunk(&self, chunk: Pos2i) -> Result<Vec<Tile>, LoadError> {
+        let file = match File::open(chunk_path(&self.directory, chunk)) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Ok(vec![TILE_AIR; (CHUNK_SIZE * CHUNK_SIZE) as usize])
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let grid = Grid::load_with_materials(BufReader::new(file), self.grid.materials().clone())?;
+        let expected = (CHUNK_SIZE as u64, CHUNK_SIZE as u64);
+
+        if grid.size() != expected {
+            return Err(LoadError::SizeMismatch {
+                expected,
+                found: grid.size(),
+            });
+        }
+
+        Ok(grid.snapshot())
+    }
+}
+
+/// Returns the size in tiles of the loaded area around a simulated area of the given number of chunks.
+fn loaded_size(area: (u64, u64)) -> (u64, u64) {
+    let border = 2 * BORDER_CHUNKS as u64;
+
+    (
+        (area.0.max(1) + border) * CHUNK_SIZE as u64,
+        (area.1.max(1) + border) * CHUNK_SIZE as u64,
+    )
+}
+
+fn chunk_path(directory: &Path, chunk: Pos2i) -> PathBuf {
+    directory.join(format!("{}_{}.bin", chunk.x, chunk.y))
+}
+
+/// Writes the tiles of a chunk to disk, chunks only made of air being removed instead.
+/// The chunk is saved with the seed and materials of the given grid.
+fn save_chunk(directory: &Path, grid: &Grid, chunk: Pos2i, tiles: &[Tile]) -> io::Result<()> {
+    let path = chunk_path(directory, chunk);
+
+    if tiles.iter().all(|tile| *tile == TILE_AIR) {
+        return match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+    }
+
+    let mut chunk_grid = Grid::with_materials(
+        (CHUNK_SIZE as u64, CHUNK_SIZE as u64),
+        grid.seed(),
+        grid.materials().clone(),
+    );
+    chunk_grid.restore(tiles.to_vec());
+    chunk_grid.save(BufWriter::new(File::create(path)?))
+}
+
+/// Copies the tiles of a chunk into the tiles of the area of the given size whose top left tile is at the
+/// given position.
+fn copy_chunk(
+    tiles: &mut [Tile],
+    size: (u64, u64),
+    origin: Pos2i,
+    chunk: Pos2i,
+    chunk_tiles: &[Tile],
+) {
+    for (position, tile) in chunk_bounds(chunk).positions().zip(chunk_tiles) {
+        let local = position - origin;
+        tiles[(local.y * size.0 as i64 + local.x) as usize] = *tile;
+    }
+}
+
+/// Returns the coordinates of the chunk containing the given world position.
+fn chunk_of(position: Pos2i) -> Pos2i {
+    (
+        position.x.div_euclid(CHUNK_SIZE),
+        position.y.div_euclid(CHUNK_SIZE),
+    )
+        .into()
+}
+
+/// Returns the position of the top left tile of an area of the given size centered on the given position,
+/// aligned on chunks.
+fn area_origin(size: (u64, u64), center: Pos2i) -> Pos2i {
+    let corner = chunk_of(center - (size.0 as i64 / 2, size.1 as i64 / 2).into());
+    (corner.x * CHUNK_SIZE, corner.y * CHUNK_SIZE).into()
+}
+
+/// Returns the world positions of the tiles of the chunk at the given coordinates.
+fn chunk_bounds(chunk: Pos2i) -> Rect {
+    let min: Pos2i = (chunk.x * CHUNK_SIZE, chunk.y * CHUNK_SIZE).into();
+
+    Rect {
+        min,
+        max: min + (CHUNK_SIZE - 1, CHUNK_SIZE - 1).into(),
+    }
+}
+
+/// Splits a world position into the coordinates of its chunk and its index within the chunk tiles.
+fn split_position(position: Pos2i) -> (Pos2i, usize) {
+    let local = (
+        position.x.rem_euclid(CHUNK_SIZE),
+        position.y.rem_euclid(CHUNK_SIZE),
+    );
+
+    (
+        chunk_of(position),
+        (local.1 * CHUNK_SIZE + local.0) as usize,
+    )
+}
@@ -1,218 +1,461 @@
-use super::{grid::TILE_AIR, Grid, Pos2i, Tile, TileType};
-use rand::{thread_rng, Rng};
-
-pub fn get_color(tile_type: TileType, variant: u8) -> &'static [u8] {
-    match tile_type {
-        TileType::Sand => {
-            if variant % 2 == 0 {
-                &[220, 204, 171, 255]
-            } else {
-                &[204, 180, 149, 255]
-            }
-        }
-        TileType::Stone => {
-            if variant % 2 == 0 {
-                &[132, 132, 132, 255]
-            } else {
-                &[124, 124, 124, 255]
-            }
-        }
-        TileType::Water => &[12, 84, 220, 255],
-        TileType::Lava => &[255, 0, 0, 255],
-        TileType::Wall => {
-            if variant % 2 == 0 {
-                &[212, 212, 212, 255]
-            } else {
-                &[220, 220, 220, 255]
-            }
-        }
-        TileType::Acid => &[0, 255, 126, 255],
-        TileType::Dirt => {
-            if variant % 2 == 0 {
-                &[116, 84, 51, 255]
-            } else {
-                &[124, 92, 60, 255]
-            }
-        }
-        _ => &[0, 0, 0, 0],
-    }
-}
+use super::{Grid, Material, MaterialState, Pos2i, SimRng, Tile, TileType, Vec2f};
+use rand::Rng;
+
+/// Offsets of the neighbours a tile reacts with, in clockwise order.
+const NEIGHBOURS: [(i64, i64); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+/// The speed falling tiles gain each step, in cells per step.
+pub(crate) const GRAVITY: f32 = 0.25;
+/// The speed falling tiles can't go faster than, in cells per step.
+pub(crate) const MAX_SPEED: f32 = 8.0;
+/// The most cells a tile moves in a single step whatever its speed, which bounds how far from its chunk
+/// the update of a tile reaches during parallel steps.
+pub(crate) const MAX_CELLS_PER_STEP: i64 = 12;
+/// The part of its vertical speed a landing tile keeps as sideways speed.
+const LANDING_SPREAD: f32 = 0.5;
+/// The part of its sideways speed a sliding tile keeps each step.
+const FRICTION: f32 = 0.75;
+/// The speed above which landing liquids splash back up.
+const SPLASH_SPEED: f32 = 3.0;
+/// The part of its landing speed a splashing liquid bounces back up with.
+const SPLASH_BOUNCE: f32 = 0.4;
 
 /// Returns a random direction which can be interpreted on the X or Y axis
-fn random_direction() -> i64 {
-    if thread_rng().gen_bool(0.5) {
+fn random_direction(rng: &mut SimRng) -> i64 {
+    if rng.gen_bool(0.5) {
         1
     } else {
         -1
     }
 }
 
-pub fn update_falling_tile(world: &mut Grid, position: Pos2i, tile: &Tile) {
-    let dir = position + (random_direction(), 1).into();
+/// Returns whether a moving tile is heavier than the given tile and can thus swap places with it.
+/// Static and solid tiles are never displaced.
+fn can_displace(world: &Grid, tile: &Tile, other: Tile) -> bool {
+    let materials = world.materials();
+    let other_material = materials.get(other.tile_type);
 
-    if let Some(down_tile) = world.get_tile(position + (0, 1).into()) {
-        if down_tile.tile_type == TileType::Air || down_tile.tile_type == TileType::Water {
-            world.set_tile(position + (0, 1).into(), *tile);
-            world.set_tile(position, down_tile);
-            return;
+    match other_material.state {
+        MaterialState::Powder | MaterialState::Liquid | MaterialState::Gas => {
+            materials.get(tile.tile_type).density > other_material.density
         }
+        MaterialState::Solid | MaterialState::Static => false,
     }
+}
 
-    if let Some(next_tile) = world.get_tile(dir) {
-        if next_tile.tile_type == TileType::Air || next_tile.tile_type == TileType::Water {
-            world.set_tile(dir, *tile);
-            world.set_tile(position, TILE_AIR);
-            return;
+/// Moves the tile to the given position if it can displace the tile there.
+/// Returns whether the tile moved.
+fn try_displace(world: &mut Grid, position: Pos2i, target: Pos2i, tile: &Tile) -> bool {
+    match world.get_tile(target) {
+        Some(other) if can_displace(world, tile, other) => {
+            world.set_tile(target, *tile);
+            world.set_tile(position, other);
+            true
         }
+        _ => false,
     }
 }
 
-pub fn update_fluid(world: &mut Grid, position: Pos2i, tile: &Tile) {
-    if let Some(down_tile) = world.get_tile(position + (0, 1).into()) {
-        if down_tile.tile_type == TileType::Air {
-            world.set_tile(position + (0, 1).into(), *tile);
-            world.set_tile(position, TILE_AIR);
-            return;
-        }
+pub(crate) fn update_falling_tile(
+    world: &mut Grid,
+    rng: &mut SimRng,
+    position: Pos2i,
+    tile: &Tile,
+) {
+    let mut moving = *tile;
+
+    if fall(world, position, &mut moving) {
+        return;
     }
 
-    let direction = random_direction();
-    let diag_pos = (direction, 1).into();
+    land(rng, &mut moving);
+    let direction = slide_direction(rng, &moving);
+
+    if try_displace(world, position, position + (direction, 1).into(), &moving) {
+        return;
+    }
 
-    if let Some(next_diag_tile) = world.get_tile(position + diag_pos) {
-        if next_diag_tile.tile_type == TileType::Air {
-            world.set_tile(position + diag_pos, *tile);
-            world.set_tile(position, TILE_AIR);
+    // powders which landed fast keep sliding sideways until friction stops them
+    if moving.velocity.x.abs() >= 1.0 {
+        slow_down(&mut moving);
+        if try_displace(world, position, position + (direction, 0).into(), &moving) {
             return;
         }
     }
 
-    let next_pos = (direction, 0).into();
+    moving.velocity.x = 0.0;
+    rest(world, position, tile, moving);
+}
+
+pub(crate) fn update_fluid(world: &mut Grid, rng: &mut SimRng, position: Pos2i, tile: &Tile) {
+    let mut moving = *tile;
+
+    if fall(world, position, &mut moving) {
+        return;
+    }
+
+    let speed = land(rng, &mut moving);
+    let direction = slide_direction(rng, &moving);
 
-    if let Some(next_tile) = world.get_tile(position + next_pos) {
-        if next_tile.tile_type == TileType::Air {
-            world.set_tile(position + next_pos, *tile);
-            world.set_tile(position, TILE_AIR);
+    // fast liquids splash back up as particles which settle back once they land
+    if speed > SPLASH_SPEED
+        && world
+            .get_tile(position + (0, -1).into())
+            .map(|t| t.tile_type)
+            == Some(TileType::AIR)
+    {
+        world.launch_tile(position, (moving.velocity.x, -speed * SPLASH_BOUNCE).into());
+        return;
+    }
+
+    if try_displace(world, position, position + (direction, 1).into(), &moving) {
+        return;
+    }
+
+    // fluids only spread sideways through gases so liquid layers stay in place,
+    // flowing further the faster they move
+    let distance = cells(moving.velocity).0.abs().max(1);
+    slow_down(&mut moving);
+
+    let moved = move_along(
+        world,
+        position,
+        (direction * distance, 0),
+        &moving,
+        |world, _, other| world.materials().get(other.tile_type).state == MaterialState::Gas,
+    );
+
+    if moved == position {
+        moving.velocity.x = 0.0;
+        rest(world, position, tile, moving);
+    }
+}
+
+/// Accelerates the tile and moves it along its velocity if it can fall or is thrown upward.
+/// Returns whether the tile is in the air, in which case it has been handled.
+fn fall(world: &mut Grid, position: Pos2i, tile: &mut Tile) -> bool {
+    let can_fall = world
+        .get_tile(position + (0, 1).into())
+        .is_some_and(|below| can_displace(world, tile, below));
+
+    if !can_fall && tile.velocity.y >= 0.0 {
+        return false;
+    }
+
+    tile.velocity.y = (tile.velocity.y + GRAVITY).min(MAX_SPEED);
+
+    if move_along(world, position, cells(tile.velocity), tile, can_displace) == position {
+        // the tile is blocked right away and loses its momentum
+        tile.velocity = Vec2f::default();
+
+        if !try_displace(world, position, position + (0, 1).into(), tile) {
+            world.set_tile(position, *tile);
         }
     }
+
+    true
 }
 
-pub fn update_acid(world: &mut Grid, position: Pos2i, tile: &Tile) {
-    if let Some(right_tile) = world.get_tile(position + (1, 0).into()) {
-        if right_tile.tile_type != TileType::Air
-            && right_tile.tile_type != TileType::Acid
-            && right_tile.tile_type != TileType::Wall
-        {
-            world.set_tile(position + (1, 0).into(), TILE_AIR);
-            world.set_tile(position, TILE_AIR);
-            return;
+/// Turns the vertical speed of a tile which stopped falling into sideways speed.
+/// Returns the speed the tile landed at.
+fn land(rng: &mut SimRng, tile: &mut Tile) -> f32 {
+    let speed = tile.velocity.y.max(0.0);
+    tile.velocity.y = 0.0;
+
+    if speed > 0.0 {
+        let direction = if tile.velocity.x == 0.0 {
+            random_direction(rng) as f32
+        } else {
+            tile.velocity.x.signum()
+        };
+
+        tile.velocity.x += direction * speed * LANDING_SPREAD;
+    }
+
+    speed
+}
+
+/// Returns the direction a tile slides to: the one it is moving in or a random one if it isn't.
+fn slide_direction(rng: &mut SimRng, tile: &Tile) -> i64 {
+    if tile.velocity.x == 0.0 {
+        random_direction(rng)
+    } else {
+        tile.velocity.x.signum() as i64
+    }
+}
+
+fn slow_down(tile: &mut Tile) {
+    tile.velocity.x *= FRICTION;
+}
+
+/// Stores the velocity of a tile which didn't move, leaving the grid untouched if it didn't change.
+fn rest(world: &mut Grid, position: Pos2i, tile: &Tile, resting: Tile) {
+    if resting.velocity != tile.velocity {
+        world.set_tile(position, resting);
+    }
+}
+
+/// Returns the whole number of cells a tile moving at the given velocity covers in a step,
+/// at most [`MAX_CELLS_PER_STEP`] along each axis.
+fn cells(velocity: Vec2f) -> (i64, i64) {
+    let cells =
+        |speed: f32| (speed.abs().ceil() as i64).min(MAX_CELLS_PER_STEP) * speed.signum() as i64;
+    (cells(velocity.x), cells(velocity.y))
+}
+
+/// Moves the tile along a line to the given offset, stopping before the first tile it can't pass through.
+/// The tile swaps places with the one it stops at. Returns the position the tile ended up at.
+fn move_along(
+    world: &mut Grid,
+    position: Pos2i,
+    offset: (i64, i64),
+    tile: &Tile,
+    passes: impl Fn(&Grid, &Tile, Tile) -> bool,
+) -> Pos2i {
+    let mut end = position;
+
+    for next in position.line_to(position + offset.into()).skip(1) {
+        match world.get_tile(next) {
+            Some(other) if passes(world, tile, other) => end = next,
+            _ => break,
         }
     }
 
-    if let Some(left_tile) = world.get_tile(position + (-1, 0).into()) {
-        if left_tile.tile_type != TileType::Air
-            && left_tile.tile_type != TileType::Acid
-            && left_tile.tile_type != TileType::Wall
-        {
-            world.set_tile(position + (-1, 0).into(), TILE_AIR);
-            world.set_tile(position, TILE_AIR);
-            return;
+    if let Some(other) = world.get_tile(end).filter(|_| end != position) {
+        world.set_tile(end, *tile);
+        world.set_tile(position, other);
+    }
+
+    end
+}
+
+pub(crate) fn update_gas(world: &mut Grid, rng: &mut SimRng, position: Pos2i, tile: &Tile) {
+    if try_rise(world, position, position + (0, -1).into(), tile) {
+        return;
+    }
+
+    let direction = random_direction(rng);
+
+    if try_rise(world, position, position + (direction, -1).into(), tile) {
+        return;
+    }
+
+    // gases diffuse sideways through each other regardless of their density
+    let next_pos = position + (direction, 0).into();
+
+    if let Some(next_tile) = world.get_tile(next_pos) {
+        if world.materials().get(next_tile.tile_type).state == MaterialState::Gas {
+            world.set_tile(next_pos, *tile);
+            world.set_tile(position, next_tile);
         }
     }
+}
 
-    if let Some(up_tile) = world.get_tile(position + (0, 1).into()) {
-        if up_tile.tile_type != TileType::Air
-            && up_tile.tile_type != TileType::Acid
-            && up_tile.tile_type != TileType::Wall
-        {
-            world.set_tile(position + (0, 1).into(), TILE_AIR);
-            world.set_tile(position, TILE_AIR);
-            return;
+/// Moves the tile up to the given position if the tile there is a heavier liquid or gas.
+/// Returns whether the tile moved.
+fn try_rise(world: &mut Grid, position: Pos2i, target: Pos2i, tile: &Tile) -> bool {
+    let other = match world.get_tile(target) {
+        Some(other) => other,
+        None => return false,
+    };
+
+    let materials = world.materials();
+    let other_material = materials.get(other.tile_type);
+
+    let rises = match other_material.state {
+        MaterialState::Liquid | MaterialState::Gas => {
+            other_material.density > materials.get(tile.tile_type).density
         }
+        _ => false,
+    };
+
+    if rises {
+        world.set_tile(target, *tile);
+        world.set_tile(position, other);
     }
 
-    if let Some(down_tile) = world.get_tile(position + (0, -1).into()) {
-        if down_tile.tile_type != TileType::Air
-            && down_tile.tile_type != TileType::Acid
-            && down_tile.tile_type != TileType::Wall
-        {
-            world.set_tile(position + (0, -1).into(), TILE_AIR);
-            world.set_tile(position, TILE_AIR);
-            return;
+    rises
+}
+
+/// Turns the tile into what its material decays into once its lifetime is over.
+/// Lifetimes are random with the material lifetime as their average.
+/// Returns whether the tile decayed.
+pub(crate) fn decay(
+    world: &mut Grid,
+    rng: &mut SimRng,
+    position: Pos2i,
+    tile: &Tile,
+    material: &Material,
+) -> bool {
+    match material.lifetime {
+        Some(lifetime) if rng.gen_ratio(1, lifetime) => {
+            world.set_tile(
+                position,
+                Tile {
+                    tile_type: material.decays_into,
+                    ..*tile
+                },
+            );
+            true
         }
+        _ => false,
     }
+}
 
-    update_fluid(world, position, tile);
+/// Sets the flammable neighbours of the tile on fire if its material ignites them.
+pub(crate) fn ignite(world: &mut Grid, rng: &mut SimRng, position: Pos2i, material: &Material) {
+    if material.ignites {
+        set_neighbours_on_fire(world, rng, position);
+    }
 }
 
-pub fn update_water(world: &mut Grid, position: Pos2i, tile: &Tile) {
-    if let Some(water_tile) = neigbour_of_type(world, position, TileType::Lava) {
+/// Burns a tile which is on fire: it spreads the fire to its flammable neighbours, releases flames
+/// around it and turns into what its material burns into once burnt out.
+/// Returns whether the tile burnt out.
+pub(crate) fn burn(
+    world: &mut Grid,
+    rng: &mut SimRng,
+    position: Pos2i,
+    tile: &Tile,
+    material: &Material,
+) -> bool {
+    // the tile may have turned into a material which can't burn since it caught fire
+    if material.flammability == 0.0 {
         world.set_tile(
             position,
             Tile {
-                variant: tile.variant,
-                tile_type: TileType::Stone,
+                burning: false,
+                ..*tile
             },
         );
-        world.set_tile(
-            position + water_tile,
-            Tile {
-                variant: tile.variant,
-                tile_type: TileType::Stone,
-            },
-        );
-        return;
+        return false;
     }
 
-    update_fluid(world, position, tile);
-}
+    if rng.gen_ratio(1, material.burn_duration) {
+        // explosions can reach further than the tiles a parallel step lets this update touch
+        if let Some((radius, power)) = material.explosion {
+            world.explosions.push((position, radius, power));
+        }
 
-pub fn update_lava(world: &mut Grid, position: Pos2i, tile: &Tile) {
-    if let Some(water_tile) = neigbour_of_type(world, position, TileType::Water) {
         world.set_tile(
             position,
             Tile {
-                variant: tile.variant,
-                tile_type: TileType::Stone,
+                tile_type: material.burns_into,
+                burning: false,
+                ..*tile
             },
         );
-        world.set_tile(
-            position + water_tile,
-            Tile {
-                variant: tile.variant,
-                tile_type: TileType::Stone,
-            },
-        );
-        return;
+        return true;
     }
 
-    update_fluid(world, position, tile);
-}
+    set_neighbours_on_fire(world, rng, position);
 
-fn neigbour_of_type(world: &mut Grid, pos: Pos2i, tile_type: TileType) -> Option<Pos2i> {
-    if let Some(right_tile) = world.get_tile(pos + (1, 0).into()) {
-        if right_tile.tile_type == tile_type {
-            return Some((1, 0).into());
+    if let Some(flame) = material.flame {
+        let flame_pos = position + NEIGHBOURS[rng.gen_range(0..NEIGHBOURS.len())].into();
+
+        if world.get_tile(flame_pos).map(|t| t.tile_type) == Some(TileType::AIR) {
+            let flame = world.materials().create_tile(flame, tile.variant);
+            world.set_tile(flame_pos, flame);
         }
     }
 
-    if let Some(left_tile) = world.get_tile(pos + (-1, 0).into()) {
-        if left_tile.tile_type == tile_type {
-            return Some((-1, 0).into());
+    false
+}
+
+/// Sets each flammable neighbour of the given position on fire with the chance of its flammability.
+fn set_neighbours_on_fire(world: &mut Grid, rng: &mut SimRng, position: Pos2i) {
+    for &offset in &NEIGHBOURS {
+        let neighbour_pos = position + offset.into();
+
+        if let Some(neighbour) = world.get_tile(neighbour_pos) {
+            let flammability = world.materials().get(neighbour.tile_type).flammability;
+
+            if !neighbour.burning && flammability > 0.0 && rng.gen_bool(flammability) {
+                world.set_tile(
+                    neighbour_pos,
+                    Tile {
+                        burning: true,
+                        ..neighbour
+                    },
+                );
+            }
         }
     }
+}
 
-    if let Some(up_tile) = world.get_tile(pos + (0, 1).into()) {
-        if up_tile.tile_type == tile_type {
-            return Some((0, 1).into());
-        }
+/// Applies the first reaction of the tile material matching one of its neighbours.
+/// Neighbours are scanned from a random starting direction so reactions don't favour any side.
+/// Products start at the temperature of their own material, keeping the velocity and flags of the tile
+/// they replace. Returns whether a reaction happened.
+pub(crate) fn react(
+    world: &mut Grid,
+    rng: &mut SimRng,
+    position: Pos2i,
+    tile: &Tile,
+    material: &Material,
+) -> bool {
+    if material.reactions.is_empty() {
+        return false;
     }
 
-    if let Some(down_tile) = world.get_tile(pos + (0, -1).into()) {
-        if down_tile.tile_type == tile_type {
-            return Some((1, 0).into());
+    let start = rng.gen_range(0..NEIGHBOURS.len());
+
+    for reaction in &material.reactions {
+        for i in 0..NEIGHBOURS.len() {
+            let neighbour_pos = position + NEIGHBOURS[(start + i) % NEIGHBOURS.len()].into();
+
+            let neighbour = match world.get_tile(neighbour_pos) {
+                Some(neighbour) if neighbour.tile_type == reaction.with => neighbour,
+                _ => continue,
+            };
+
+            if !rng.gen_bool(reaction.probability) {
+                continue;
+            }
+
+            let product = reaction_product(world, reaction.becomes, tile);
+            world.set_tile(position, product);
+            let product = reaction_product(world, reaction.other_becomes, &neighbour);
+            world.set_tile(neighbour_pos, product);
+
+            if let Some(byproduct) = reaction.byproduct {
+                let byproduct = reaction_product(world, byproduct, tile);
+                spawn_around(world, start, position, byproduct);
+            }
+
+            return true;
         }
     }
 
-    None
+    false
+}
+
+/// Returns a new tile of the given type replacing the given one in a reaction.
+fn reaction_product(world: &Grid, tile_type: TileType, replaced: &Tile) -> Tile {
+    Tile {
+        burning: replaced.burning,
+        velocity: replaced.velocity,
+        ..world.materials().create_tile(tile_type, replaced.variant)
+    }
+}
+
+/// Places the tile in the first empty cell around the given position, if any.
+fn spawn_around(world: &mut Grid, start: usize, position: Pos2i, tile: Tile) {
+    for i in 0..NEIGHBOURS.len() {
+        let pos = position + NEIGHBOURS[(start + i) % NEIGHBOURS.len()].into();
+
+        if world.get_tile(pos).map(|t| t.tile_type) == Some(TileType::AIR) {
+            world.set_tile(pos, tile);
+            return;
+        }
+    }
 }
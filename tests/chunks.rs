@@ -0,0 +1,43 @@
+use poussiere::{Grid, CHUNK_SIZE};
+
+/// Drops a pile of sand in the middle of a grid and lets it settle, checking every chunk falls asleep.
+#[test]
+fn settled_piles_fall_asleep() {
+    for step in [Grid::step, Grid::step_sequential] {
+        let mut grid = Grid::with_seed((128, 128), 11);
+        let materials = grid.materials().clone();
+        let sand = materials.find("Sand").unwrap();
+
+        for y in 0..48 {
+            for x in 40..88 {
+                let tile = materials.create_tile(sand, (x + y) as u8 % 2);
+                grid.set_tile((x, y).into(), tile);
+            }
+        }
+
+        step(&mut grid);
+        assert!(grid.awake_chunks() > 0);
+
+        for _ in 0..600 {
+            step(&mut grid);
+        }
+
+        assert_eq!(grid.awake_chunks(), 0);
+
+        // an edit only wakes the chunks around it up
+        grid.set_tile((CHUNK_SIZE + 4, 4).into(), materials.create_tile(sand, 0));
+        step(&mut grid);
+        assert_eq!(grid.awake_chunks(), 1);
+    }
+}
+
+#[test]
+fn empty_grids_step() {
+    for step in [Grid::step, Grid::step_sequential] {
+        for size in [(0, 5), (5, 0), (0, 0)] {
+            let mut grid = Grid::new(size);
+            step(&mut grid);
+            assert_eq!(grid.awake_chunks(), 0);
+        }
+    }
+}
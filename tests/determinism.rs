@@ -0,0 +1,33 @@
+use poussiere::{Grid, Tile};
+
+/// Simulates a grid with some of every material on a pool of the given number of threads.
+fn simulate(threads: usize) -> (Vec<Tile>, usize) {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+
+    pool.install(|| {
+        let mut grid = Grid::with_seed((96, 64), 7);
+        let materials = grid.materials().clone();
+        let types: Vec<_> = materials.iter().map(|(tile_type, _)| tile_type).collect();
+
+        for y in 0..32 {
+            for x in 0..96 {
+                let tile_type = types[(x / 8 + y / 6) as usize % types.len()];
+                grid.set_tile((x, y).into(), materials.create_tile(tile_type, 0));
+            }
+        }
+
+        for _ in 0..150 {
+            grid.step();
+        }
+
+        (grid.snapshot(), grid.particles().len())
+    })
+}
+
+#[test]
+fn parallel_step_does_not_depend_on_thread_count() {
+    assert_eq!(simulate(1), simulate(4));
+}
@@ -0,0 +1,37 @@
+use poussiere::{Grid, MaterialRegistry, TileType};
+use std::sync::Arc;
+
+/// A charge blowing up with a radius larger than a chunk as soon as it burns, and a target to destroy.
+const MATERIALS: &str = r#"(
+    materials: [
+        (name: "Air", colors: [(0, 0, 0, 0)], density: 0.0, state: Gas),
+        (name: "Charge", colors: [(255, 0, 0, 255)], density: 1.0, state: Solid,
+            flammability: 1.0, explosion: Some((48.0, 100.0))),
+        (name: "Target", colors: [(0, 0, 255, 255)], density: 1.0, state: Solid),
+    ],
+)"#;
+
+/// Sets off a charge in the middle of a grid ringed with targets, checking all of them are destroyed.
+#[test]
+fn explosions_reach_their_radius() {
+    let materials = Arc::new(MaterialRegistry::from_ron(MATERIALS).unwrap());
+
+    for step in [Grid::step, Grid::step_sequential] {
+        let mut grid = Grid::with_materials((128, 128), 1, materials.clone());
+
+        let mut charge = materials.create_tile(materials.find("Charge").unwrap(), 0);
+        charge.burning = true;
+        grid.set_tile((64, 64).into(), charge);
+
+        let target = materials.create_tile(materials.find("Target").unwrap(), 0);
+        for (x, y) in [(64, 24), (64, 104), (24, 64), (104, 64), (36, 36), (92, 92)] {
+            grid.set_tile((x, y).into(), target);
+        }
+
+        step(&mut grid);
+
+        assert!(grid
+            .iter_tiles()
+            .all(|(_, tile)| tile.tile_type == TileType::AIR));
+    }
+}
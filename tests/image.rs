@@ -0,0 +1,88 @@
+use png::{BitDepth, ColorType, Encoder};
+use poussiere::{Grid, ImportMode, TileType};
+
+/// Encodes the given RGBA pixels as a PNG image of the given width.
+fn png(width: u32, pixels: &[[u8; 4]]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let height = pixels.len() as u32 / width;
+
+    let mut encoder = Encoder::new(&mut bytes, width, height);
+    encoder.set_color(ColorType::RGBA);
+    encoder.set_depth(BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(&pixels.concat())
+        .unwrap();
+
+    bytes
+}
+
+#[test]
+fn exported_worlds_import_back() {
+    let mut grid = Grid::new((24, 8));
+    let materials = grid.materials().clone();
+
+    // every variant of every material once, air included
+    let tiles: Vec<_> = materials
+        .iter()
+        .flat_map(|(tile_type, material)| {
+            (0..material.colors.len()).map(move |variant| (tile_type, variant as u8))
+        })
+        .collect();
+
+    for (i, (tile_type, variant)) in tiles.iter().enumerate() {
+        let position = ((i % 24) as i64, (i / 24) as i64);
+        grid.set_tile(position.into(), materials.create_tile(*tile_type, *variant));
+    }
+
+    for scale in [1, 3] {
+        let mut bytes = Vec::new();
+        grid.export_png(&mut bytes, scale).unwrap();
+
+        let mut imported = Grid::new((24, 8));
+        imported.import_png(&bytes[..], ImportMode::Scale).unwrap();
+
+        assert_eq!(imported.snapshot(), grid.snapshot());
+    }
+}
+
+#[test]
+fn only_transparent_pixels_are_imported_as_air() {
+    let mut grid = Grid::new((4, 1));
+    let bytes = png(
+        4,
+        &[
+            [0, 0, 0, 0],
+            [220, 204, 171, 0],
+            [0, 0, 0, 255],
+            [220, 204, 171, 200],
+        ],
+    );
+
+    grid.import_png(&bytes[..], ImportMode::Crop).unwrap();
+
+    let sand = grid.materials().find("Sand").unwrap();
+    let types: Vec<TileType> = grid.iter_tiles().map(|(_, tile)| tile.tile_type).collect();
+
+    assert_eq!(types[0], TileType::AIR);
+    assert_eq!(types[1], TileType::AIR);
+    assert_ne!(types[2], TileType::AIR);
+    assert_eq!(types[3], sand);
+}
+
+#[test]
+fn cropped_images_leave_the_rest_of_the_grid_empty() {
+    let mut grid = Grid::new((3, 2));
+    let bytes = png(1, &[[132, 132, 132, 255]]);
+
+    grid.import_png(&bytes[..], ImportMode::Crop).unwrap();
+
+    let stone = grid.materials().find("Stone").unwrap();
+    let types: Vec<TileType> = grid.iter_tiles().map(|(_, tile)| tile.tile_type).collect();
+
+    assert_eq!(types[0], stone);
+    assert!(types[1..]
+        .iter()
+        .all(|tile_type| *tile_type == TileType::AIR));
+}
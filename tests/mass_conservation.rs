@@ -0,0 +1,90 @@
+use poussiere::{Grid, Particle, TileType};
+use std::collections::HashMap;
+
+/// Counts the tiles of each material but air, including the ones flying as particles.
+fn material_counts(grid: &Grid) -> HashMap<TileType, usize> {
+    let mut counts = HashMap::new();
+
+    let tiles = grid.iter_tiles().map(|(_, tile)| tile);
+    let particles = grid.particles().iter().map(|particle| &particle.tile);
+
+    for tile in tiles
+        .chain(particles)
+        .filter(|tile| tile.tile_type != TileType::AIR)
+    {
+        *counts.entry(tile.tile_type).or_insert(0) += 1;
+    }
+
+    counts
+}
+
+/// Lets a loose mix of inert materials settle, checking nothing is lost.
+#[test]
+fn inert_materials_are_conserved() {
+    for step in [Grid::step, Grid::step_sequential] {
+        let mut grid = Grid::with_seed((64, 64), 42);
+        let materials = grid.materials().clone();
+
+        let inert: Vec<TileType> = ["Sand", "Dirt", "Stone", "Water", "Oil"]
+            .iter()
+            .map(|name| materials.find(name).unwrap())
+            .collect();
+
+        // a loose mix of materials in the upper half of the grid, with air gaps to fall through
+        for y in 0..32 {
+            for x in 0..64 {
+                if (x + y) % 5 != 0 {
+                    let tile_type = inert[(x * 7 + y * 3) as usize % inert.len()];
+                    grid.set_tile((x, y).into(), materials.create_tile(tile_type, 0));
+                }
+            }
+        }
+
+        let before = material_counts(&grid);
+
+        for _ in 0..500 {
+            step(&mut grid);
+            assert_eq!(material_counts(&grid), before);
+        }
+    }
+}
+
+/// Throws particles over a grid whose columns are mostly full, some of them flying off its sides,
+/// checking every one of them lands back somewhere.
+#[test]
+fn particles_are_conserved() {
+    for step in [Grid::step, Grid::step_sequential] {
+        let mut grid = Grid::with_seed((48, 32), 5);
+        let materials = grid.materials().clone();
+        let wall = materials.create_tile(materials.find("Wall").unwrap(), 0);
+        let sand = materials.create_tile(materials.find("Sand").unwrap(), 0);
+
+        // every column is full but a few cells at the bottom right
+        for y in 0..32 {
+            for x in 0..48 {
+                if x < 44 || y < 28 {
+                    grid.set_tile((x, y).into(), wall);
+                }
+            }
+        }
+
+        for i in 0..40 {
+            let speed = (i % 5) as f32 - 2.0;
+            grid.spawn_particle(Particle::new(
+                (i % 48, -1 - i / 8).into(),
+                (speed * 1.5, -2.0 - (i % 3) as f32).into(),
+                sand,
+            ));
+        }
+
+        let before = material_counts(&grid);
+
+        for _ in 0..300 {
+            step(&mut grid);
+            assert_eq!(material_counts(&grid), before);
+        }
+
+        // sixteen of them found room in the grid, the others wait above it
+        assert_eq!(grid.particles().len(), 40 - 16);
+    }
+}
@@ -0,0 +1,244 @@
+use poussiere::{MaterialRegistry, MaterialState, RegistryError, TileType, AMBIENT_TEMPERATURE};
+
+/// Wraps the given material and reaction definitions into a materials file, after an air material.
+fn materials_file(materials: &str, reactions: &str) -> String {
+    format!(
+        r#"(
+            materials: [
+                (name: "Air", colors: [(0, 0, 0, 0)], density: 0.0, state: Gas),
+                {}
+            ],
+            reactions: [{}],
+        )"#,
+        materials, reactions
+    )
+}
+
+fn registry(materials: &str, reactions: &str) -> Result<MaterialRegistry, RegistryError> {
+    MaterialRegistry::from_ron(&materials_file(materials, reactions))
+}
+
+#[test]
+fn builtin_materials_are_valid() {
+    let materials = MaterialRegistry::builtin();
+
+    assert_eq!(materials.find("Air"), Some(TileType::AIR));
+    assert!(materials.find("Sand").is_some());
+}
+
+#[test]
+fn materials_are_read_from_ron() {
+    let materials = registry(
+        r#"(name: "Rock", colors: [(1, 2, 3, 255), (4, 5, 6, 255)], density: 3.0, state: Powder,
+            temperature: 80.0, heats_into: Some((900.0, "Magma"))),
+           (name: "Magma", colors: [(255, 0, 0, 255)], density: 3.0, state: Liquid)"#,
+        r#"(material: "Magma", with: "Rock", probability: 0.5, becomes: "Rock", other_becomes: "Magma")"#,
+    )
+    .unwrap();
+
+    assert_eq!(materials.len(), 3);
+
+    let rock = materials.find("Rock").unwrap();
+    let magma = materials.find("Magma").unwrap();
+    assert_eq!(rock, TileType(1));
+    assert_eq!(magma, TileType(2));
+    assert_eq!(materials.find("Granite"), None);
+
+    let material = materials.get(rock);
+    assert_eq!(material.state, MaterialState::Powder);
+    assert_eq!(material.heats_into, Some((900.0, magma)));
+    assert_eq!(materials.color(rock, 1), &[4, 5, 6, 255]);
+    // variants wrap around the colors of their material
+    assert_eq!(materials.color(rock, 2), &[1, 2, 3, 255]);
+
+    let reactions = &materials.get(magma).reactions;
+    assert_eq!(reactions.len(), 1);
+    assert_eq!(reactions[0].with, rock);
+    assert_eq!(reactions[0].probability, 0.5);
+}
+
+#[test]
+fn tiles_are_created_at_the_temperature_of_their_material() {
+    let materials = registry(
+        r#"(name: "Ember", colors: [(255, 80, 0, 255)], density: 1.0, state: Powder, temperature: 600.0),
+           (name: "Pebble", colors: [(90, 90, 90, 255)], density: 2.0, state: Powder)"#,
+        "",
+    )
+    .unwrap();
+
+    let ember = materials.find("Ember").unwrap();
+    let tile = materials.create_tile(ember, 3);
+
+    assert_eq!(tile.tile_type, ember);
+    assert_eq!(tile.variant, 3);
+    assert_eq!(tile.temperature, 600.0);
+    assert!(!tile.burning);
+
+    let pebble = materials.create_tile(materials.find("Pebble").unwrap(), 0);
+    assert_eq!(pebble.temperature, AMBIENT_TEMPERATURE);
+}
+
+#[test]
+fn unknown_tile_types_are_treated_as_air() {
+    let materials = MaterialRegistry::builtin();
+
+    assert_eq!(materials.get(TileType(255)).name, "Air");
+}
+
+#[test]
+#[allow(deprecated)]
+fn deprecated_colors_come_from_the_builtin_materials() {
+    let materials = MaterialRegistry::builtin();
+    let sand = materials.find("Sand").unwrap();
+
+    assert_eq!(poussiere::get_color(sand, 1), &materials.color(sand, 1)[..]);
+}
+
+#[test]
+fn missing_files_are_io_errors() {
+    assert!(matches!(
+        MaterialRegistry::load_file("does/not/exist.ron"),
+        Err(RegistryError::Io(_))
+    ));
+}
+
+#[test]
+fn invalid_ron_is_a_parse_error() {
+    assert!(matches!(
+        MaterialRegistry::from_ron("(materials: [(name: "),
+        Err(RegistryError::Parse(_))
+    ));
+}
+
+#[test]
+fn registries_need_a_material() {
+    assert!(matches!(
+        MaterialRegistry::from_ron("(materials: [])"),
+        Err(RegistryError::Empty)
+    ));
+}
+
+#[test]
+fn registries_hold_at_most_256_materials() {
+    let materials: Vec<String> = (0..256)
+        .map(|i| {
+            format!(
+                r#"(name: "M{}", colors: [(0, 0, 0, 255)], density: 1.0, state: Solid)"#,
+                i
+            )
+        })
+        .collect();
+
+    assert!(matches!(
+        registry(&materials.join(","), ""),
+        Err(RegistryError::TooManyMaterials(257))
+    ));
+}
+
+#[test]
+fn materials_need_a_color() {
+    assert!(matches!(
+        registry(r#"(name: "Ghost", colors: [], density: 1.0, state: Solid)"#, ""),
+        Err(RegistryError::NoColor(name)) if name == "Ghost"
+    ));
+}
+
+#[test]
+fn material_names_are_unique() {
+    assert!(matches!(
+        registry(r#"(name: "Air", colors: [(0, 0, 0, 0)], density: 1.0, state: Gas)"#, ""),
+        Err(RegistryError::DuplicateMaterial(name)) if name == "Air"
+    ));
+}
+
+#[test]
+fn referenced_materials_have_to_exist() {
+    assert!(matches!(
+        registry(
+            r#"(name: "Ice", colors: [(0, 0, 255, 255)], density: 1.0, state: Solid,
+                heats_into: Some((0.0, "Slush")))"#,
+            ""
+        ),
+        Err(RegistryError::UnknownMaterial(name)) if name == "Slush"
+    ));
+
+    assert!(matches!(
+        registry("", r#"(material: "Air", with: "Vacuum", becomes: "Air", other_becomes: "Air")"#),
+        Err(RegistryError::UnknownMaterial(name)) if name == "Vacuum"
+    ));
+}
+
+#[test]
+fn reaction_probabilities_are_between_0_and_1() {
+    assert!(matches!(
+        registry("", r#"(material: "Air", with: "Air", probability: 1.5, becomes: "Air", other_becomes: "Air")"#),
+        Err(RegistryError::InvalidProbability(probability)) if probability == 1.5
+    ));
+}
+
+#[test]
+fn rates_are_between_0_and_1() {
+    for rate in ["conductivity: 2.0", "dissipation: -0.5"] {
+        let material = format!(
+            r#"(name: "Metal", colors: [(0, 0, 0, 255)], density: 1.0, state: Solid, {})"#,
+            rate
+        );
+
+        assert!(matches!(
+            registry(&material, ""),
+            Err(RegistryError::InvalidRate(name)) if name == "Metal"
+        ));
+    }
+}
+
+#[test]
+fn flammability_is_between_0_and_1() {
+    assert!(matches!(
+        registry(
+            r#"(name: "Paper", colors: [(0, 0, 0, 255)], density: 1.0, state: Solid, flammability: 3.0)"#,
+            ""
+        ),
+        Err(RegistryError::InvalidFlammability(name)) if name == "Paper"
+    ));
+}
+
+#[test]
+fn lifetimes_last_at_least_a_step() {
+    assert!(matches!(
+        registry(
+            r#"(name: "Spark", colors: [(0, 0, 0, 255)], density: 1.0, state: Gas, lifetime: Some(0))"#,
+            ""
+        ),
+        Err(RegistryError::InvalidLifetime(name)) if name == "Spark"
+    ));
+}
+
+#[test]
+fn burn_durations_last_at_least_a_step() {
+    assert!(matches!(
+        registry(
+            r#"(name: "Fuse", colors: [(0, 0, 0, 255)], density: 1.0, state: Solid, burn_duration: 0)"#,
+            ""
+        ),
+        Err(RegistryError::InvalidBurnDuration(name)) if name == "Fuse"
+    ));
+}
+
+#[test]
+fn acid_and_water_dilute_into_each_other() {
+    let materials = MaterialRegistry::builtin();
+    let acid = materials.find("Acid").unwrap();
+    let water = materials.find("Water").unwrap();
+    let diluted = materials.find("Diluted Acid").unwrap();
+
+    let reaction = materials
+        .get(acid)
+        .reactions
+        .iter()
+        .find(|reaction| reaction.with == water)
+        .unwrap();
+
+    assert_eq!(reaction.becomes, diluted);
+    assert_eq!(reaction.other_becomes, diluted);
+    assert_eq!(reaction.byproduct, materials.find("Steam"));
+}
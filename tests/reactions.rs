@@ -0,0 +1,86 @@
+use poussiere::Grid;
+
+/// Returns how many tiles of the grid are of the given material.
+fn count(grid: &Grid, name: &str) -> usize {
+    let tile_type = grid.materials().find(name).unwrap();
+
+    grid.iter_tiles()
+        .filter(|(_, tile)| tile.tile_type == tile_type)
+        .count()
+}
+
+/// Returns a grid with a single lava tile at the bottom of a pool of water.
+fn lava_under_water() -> Grid {
+    let mut grid = Grid::with_seed((9, 6), 5);
+    let materials = grid.materials().clone();
+    let water = materials.find("Water").unwrap();
+    let lava = materials.find("Lava").unwrap();
+
+    for y in 0..6 {
+        for x in 0..9 {
+            grid.set_tile((x, y).into(), materials.create_tile(water, 0));
+        }
+    }
+    grid.set_tile((4, 5).into(), materials.create_tile(lava, 0));
+
+    grid
+}
+
+#[test]
+fn water_poured_on_lava_leaves_stone_which_stays_stone() {
+    let mut grid = lava_under_water();
+    let stone_type = grid.materials().find("Stone").unwrap();
+    let melting = grid.materials().get(stone_type).heats_into.unwrap().0;
+
+    while count(&grid, "Stone") == 0 {
+        grid.step();
+    }
+
+    // the stone starts cold rather than at the temperature of the lava and water it replaces
+    for (_, tile) in grid.iter_tiles() {
+        if tile.tile_type == stone_type {
+            assert!(tile.temperature < melting, "{:?}", tile);
+        }
+    }
+
+    for _ in 0..200 {
+        grid.step();
+    }
+
+    let stone = count(&grid, "Stone");
+    assert_eq!(count(&grid, "Lava"), 0);
+    assert!(stone > 0);
+
+    for _ in 0..200 {
+        grid.step();
+        assert_eq!(count(&grid, "Lava"), 0);
+    }
+
+    assert_eq!(count(&grid, "Stone"), stone);
+}
+
+#[test]
+fn water_poured_on_lava_boils_into_steam() {
+    let mut grid = Grid::with_seed((32, 24), 1);
+    let materials = grid.materials().clone();
+    let water = materials.find("Water").unwrap();
+    let lava = materials.find("Lava").unwrap();
+
+    for x in 0..32 {
+        for y in 8..13 {
+            grid.set_tile((x, y).into(), materials.create_tile(water, 0));
+        }
+        for y in 20..24 {
+            grid.set_tile((x, y).into(), materials.create_tile(lava, 0));
+        }
+    }
+
+    let mut most_steam = 0;
+    for _ in 0..300 {
+        grid.step();
+        most_steam = most_steam.max(count(&grid, "Steam"));
+    }
+
+    // a good part of the water boils away before it has the time to turn the lava into stone
+    assert!(most_steam > 32 * 5 / 4, "{} steam tiles", most_steam);
+}
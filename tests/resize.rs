@@ -0,0 +1,129 @@
+use poussiere::{Grid, Particle, ResizeAnchor, Tile, TileType};
+
+/// Returns a grid of the given size filled with air except for the given tiles.
+fn marked_grid(size: (u64, u64), marks: &[((i64, i64), &str)]) -> Grid {
+    let mut grid = Grid::new(size);
+    let materials = grid.materials().clone();
+
+    for (position, name) in marks {
+        let tile_type = materials.find(name).unwrap();
+        grid.set_tile((*position).into(), materials.create_tile(tile_type, 0));
+    }
+
+    grid
+}
+
+fn tile_type(grid: &Grid, position: (i64, i64)) -> TileType {
+    grid.get_tile(position.into()).unwrap().tile_type
+}
+
+fn material(grid: &Grid, name: &str) -> TileType {
+    grid.materials().find(name).unwrap()
+}
+
+/// Returns how many tiles of the grid aren't air.
+fn filled(grid: &Grid) -> usize {
+    grid.iter_tiles()
+        .filter(|(_, tile)| tile.tile_type != TileType::AIR)
+        .count()
+}
+
+#[test]
+fn growing_from_the_top_left_keeps_tiles_in_place() {
+    let mut grid = marked_grid((4, 3), &[((0, 0), "Stone"), ((3, 2), "Wall")]);
+
+    grid.resize((6, 5), ResizeAnchor::TopLeft);
+
+    assert_eq!(grid.size(), (6, 5));
+    assert_eq!(tile_type(&grid, (0, 0)), material(&grid, "Stone"));
+    assert_eq!(tile_type(&grid, (3, 2)), material(&grid, "Wall"));
+    assert_eq!(filled(&grid), 2);
+}
+
+#[test]
+fn growing_from_the_center_keeps_tiles_centered() {
+    let mut grid = marked_grid((4, 4), &[((0, 0), "Stone"), ((3, 3), "Wall")]);
+
+    grid.resize((8, 6), ResizeAnchor::Center);
+
+    assert_eq!(grid.size(), (8, 6));
+    assert_eq!(tile_type(&grid, (2, 1)), material(&grid, "Stone"));
+    assert_eq!(tile_type(&grid, (5, 4)), material(&grid, "Wall"));
+    assert_eq!(filled(&grid), 2);
+}
+
+#[test]
+fn shrinking_from_the_top_left_drops_the_right_and_bottom_sides() {
+    let mut grid = marked_grid(
+        (6, 6),
+        &[((1, 1), "Stone"), ((2, 4), "Wall"), ((5, 0), "Wall")],
+    );
+
+    grid.resize((3, 2), ResizeAnchor::TopLeft);
+
+    assert_eq!(grid.size(), (3, 2));
+    assert_eq!(tile_type(&grid, (1, 1)), material(&grid, "Stone"));
+    assert_eq!(filled(&grid), 1);
+}
+
+#[test]
+fn shrinking_from_the_center_drops_every_side() {
+    let mut grid = marked_grid(
+        (8, 8),
+        &[((3, 3), "Stone"), ((0, 0), "Wall"), ((7, 4), "Wall")],
+    );
+
+    grid.resize((4, 4), ResizeAnchor::Center);
+
+    assert_eq!(grid.size(), (4, 4));
+    assert_eq!(tile_type(&grid, (1, 1)), material(&grid, "Stone"));
+    assert_eq!(filled(&grid), 1);
+}
+
+#[test]
+fn particles_move_along_with_the_tiles() {
+    let mut grid = Grid::new((8, 8));
+    let sand = material(&grid, "Sand");
+    let tile: Tile = grid.materials().create_tile(sand, 0);
+
+    grid.spawn_particle(Particle::new((5, 1).into(), (0.0, 0.0).into(), tile));
+    grid.spawn_particle(Particle::new((0, 7).into(), (0.0, 0.0).into(), tile));
+
+    grid.resize((12, 12), ResizeAnchor::Center);
+    let cells: Vec<_> = grid.particles().iter().map(Particle::cell).collect();
+    assert_eq!(cells, [(7, 3).into(), (2, 9).into()]);
+
+    // particles left below the grid are dropped
+    grid.resize((8, 8), ResizeAnchor::TopLeft);
+    let cells: Vec<_> = grid.particles().iter().map(Particle::cell).collect();
+    assert_eq!(cells, [(7, 3).into()]);
+}
+
+#[test]
+fn resized_grids_keep_simulating() {
+    for step in [Grid::step, Grid::step_sequential] {
+        let mut grid = Grid::new((40, 40));
+        let sand = material(&grid, "Sand");
+        for x in 0..40 {
+            grid.set_tile((x, 0).into(), grid.materials().create_tile(sand, 0));
+        }
+        step(&mut grid);
+
+        for (size, anchor, chunks) in [
+            ((100, 70), ResizeAnchor::Center, 4 * 3),
+            ((33, 20), ResizeAnchor::TopLeft, 2),
+            ((64, 64), ResizeAnchor::Center, 4),
+        ] {
+            grid.resize(size, anchor);
+
+            // every chunk of the new size is redrawn and simulated
+            assert_eq!(grid.take_changed_chunks().len(), chunks);
+            step(&mut grid);
+            assert_eq!(grid.awake_chunks(), chunks);
+
+            for _ in 0..50 {
+                step(&mut grid);
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use poussiere::{Grid, LoadError, MAX_WORLD_TILES, WORLD_FORMAT_VERSION, WORLD_MAGIC};
+use std::io::{self, Read};
+
+/// Writes the header of a world file of the given format version, size and material table.
+fn header(version: u16, size: (u64, u64), names: &[&str]) -> Vec<u8> {
+    let mut bytes = WORLD_MAGIC.to_vec();
+    bytes.extend_from_slice(&version.to_le_bytes());
+    bytes.extend_from_slice(&size.0.to_le_bytes());
+    bytes.extend_from_slice(&size.1.to_le_bytes());
+    bytes.extend_from_slice(&42u64.to_le_bytes());
+
+    bytes.extend_from_slice(&(names.len() as u16).to_le_bytes());
+    for name in names {
+        bytes.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        bytes.extend_from_slice(name.as_bytes());
+    }
+
+    bytes
+}
+
+/// Writes a run of the tile section, made of tiles of the given type id without any flags.
+fn run(bytes: &mut Vec<u8>, length: u32, id: u8) {
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes.extend_from_slice(&[id, 0, 0]);
+}
+
+fn load(bytes: &[u8]) -> Result<Grid, LoadError> {
+    Grid::load(bytes)
+}
+
+#[test]
+fn saved_worlds_load_back() {
+    let mut grid = Grid::with_seed((40, 30), 9);
+    let materials = grid.materials().clone();
+
+    for (x, name) in ["Sand", "Water", "Stone", "Wood", "Lava"]
+        .iter()
+        .enumerate()
+    {
+        let tile_type = materials.find(name).unwrap();
+        for y in 10..30 {
+            grid.set_tile(
+                (x as i64 * 3, y).into(),
+                materials.create_tile(tile_type, 1),
+            );
+        }
+    }
+
+    for _ in 0..20 {
+        grid.step();
+    }
+
+    let mut bytes = Vec::new();
+    grid.save(&mut bytes).unwrap();
+    let loaded = load(&bytes).unwrap();
+
+    assert_eq!(loaded.size(), grid.size());
+    assert_eq!(loaded.seed(), grid.seed());
+
+    // temperatures and velocities are saved exactly
+    assert!(loaded.snapshot() == grid.snapshot());
+}
+
+#[test]
+fn temperatures_near_phase_changes_are_kept() {
+    let mut grid = Grid::new((2, 1));
+    let materials = grid.materials().clone();
+
+    let mut water = materials.create_tile(materials.find("Water").unwrap(), 0);
+    water.temperature = 100.4;
+    water.velocity = (0.3, -1.0 / 3.0).into();
+    grid.set_tile((1, 0).into(), water);
+
+    let mut bytes = Vec::new();
+    grid.save(&mut bytes).unwrap();
+    let loaded = load(&bytes).unwrap().get_tile((1, 0).into()).unwrap();
+
+    assert_eq!(loaded.temperature.to_bits(), water.temperature.to_bits());
+    assert_eq!(loaded.velocity.x.to_bits(), water.velocity.x.to_bits());
+    assert_eq!(loaded.velocity.y.to_bits(), water.velocity.y.to_bits());
+}
+
+#[test]
+fn settled_worlds_save_into_long_runs() {
+    let mut grid = Grid::with_seed((256, 256), 3);
+    let materials = grid.materials().clone();
+    let sand = materials.find("Sand").unwrap();
+
+    // a settled layer of sand at rest, at the temperature it was created with
+    for y in 128..256 {
+        for x in 0..256 {
+            grid.set_tile((x, y).into(), materials.create_tile(sand, 0));
+        }
+    }
+
+    let mut bytes = Vec::new();
+    grid.save(&mut bytes).unwrap();
+
+    assert!(bytes.len() < 1024, "{} bytes", bytes.len());
+}
+
+#[test]
+fn worlds_have_to_start_with_the_magic_bytes() {
+    let mut bytes = header(WORLD_FORMAT_VERSION, (1, 1), &["Air"]);
+    bytes[0] = b'X';
+
+    assert!(matches!(load(&bytes), Err(LoadError::BadMagic)));
+}
+
+#[test]
+fn unknown_versions_are_rejected() {
+    for version in [0, WORLD_FORMAT_VERSION + 1] {
+        let bytes = header(version, (1, 1), &["Air"]);
+
+        assert!(matches!(
+            load(&bytes),
+            Err(LoadError::UnsupportedVersion(found)) if found == version
+        ));
+    }
+}
+
+#[test]
+fn truncated_worlds_are_rejected() {
+    let mut grid = Grid::new((16, 16));
+    let sand = grid.materials().find("Sand").unwrap();
+    grid.set_tile((3, 3).into(), grid.materials().create_tile(sand, 0));
+
+    let mut bytes = Vec::new();
+    grid.save(&mut bytes).unwrap();
+
+    for length in [2, 10, bytes.len() - 1] {
+        assert!(matches!(load(&bytes[..length]), Err(LoadError::Truncated)));
+    }
+}
+
+#[test]
+fn oversized_worlds_are_rejected_before_allocating() {
+    for size in [(1 << 32, 1 << 32), (u64::MAX, 2), (1 << 14, 1 << 14)] {
+        let mut bytes = header(WORLD_FORMAT_VERSION, size, &["Air"]);
+        run(&mut bytes, u32::MAX, 0);
+
+        assert!(matches!(load(&bytes), Err(LoadError::TooLarge(found)) if found == size));
+    }
+}
+
+#[test]
+fn headers_claiming_more_tiles_than_the_runs_hold_are_rejected() {
+    let side = (MAX_WORLD_TILES as f64).sqrt() as u64;
+    let mut bytes = header(WORLD_FORMAT_VERSION, (side, side), &["Air"]);
+    run(&mut bytes, 16, 0);
+
+    assert!(matches!(load(&bytes), Err(LoadError::Truncated)));
+}
+
+#[test]
+fn runs_longer_than_the_world_are_rejected() {
+    let mut bytes = header(WORLD_FORMAT_VERSION, (4, 4), &["Air"]);
+    run(&mut bytes, 10, 0);
+    run(&mut bytes, u32::MAX, 0);
+
+    assert!(matches!(load(&bytes), Err(LoadError::RunOverflow)));
+}
+
+#[test]
+fn tile_ids_have_to_be_in_the_material_table() {
+    let mut bytes = header(WORLD_FORMAT_VERSION, (2, 2), &["Air", "Sand"]);
+    run(&mut bytes, 4, 2);
+
+    assert!(matches!(load(&bytes), Err(LoadError::UnknownTile(2))));
+}
+
+#[test]
+fn materials_have_to_exist_in_the_registry() {
+    let mut bytes = header(WORLD_FORMAT_VERSION, (2, 2), &["Air", "Unobtainium"]);
+    run(&mut bytes, 4, 1);
+
+    assert!(matches!(
+        load(&bytes),
+        Err(LoadError::UnknownMaterial(name)) if name == "Unobtainium"
+    ));
+}
+
+#[test]
+fn material_names_have_to_be_utf8() {
+    let mut bytes = header(WORLD_FORMAT_VERSION, (2, 2), &[]);
+    let count = bytes.len() - 2;
+    bytes[count..].copy_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&[0xff, 0xfe]);
+
+    assert!(matches!(load(&bytes), Err(LoadError::InvalidMaterialName)));
+}
+
+#[test]
+fn loading_into_a_grid_requires_the_same_size() {
+    let mut bytes = Vec::new();
+    Grid::new((8, 8)).save(&mut bytes).unwrap();
+
+    let mut grid = Grid::new((4, 4));
+
+    assert!(matches!(
+        grid.load_into(&bytes[..]),
+        Err(LoadError::SizeMismatch {
+            expected: (4, 4),
+            found: (8, 8)
+        })
+    ));
+}
+
+#[test]
+fn read_errors_are_reported() {
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Other, "disk on fire"))
+        }
+    }
+
+    assert!(matches!(Grid::load(Failing), Err(LoadError::Io(_))));
+}
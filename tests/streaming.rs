@@ -0,0 +1,166 @@
+use poussiere::{Grid, MaterialRegistry, Rect, ResizeAnchor, StreamingWorld, TileType, CHUNK_SIZE};
+use std::path::PathBuf;
+
+/// Returns an empty directory to stream a world to, unique to the given test.
+fn directory(test: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!(
+        "poussiere-streaming-{}-{}",
+        test,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&directory);
+    directory
+}
+
+#[test]
+fn chunks_out_of_range_are_streamed_to_disk() {
+    let directory = directory("range");
+    let materials = MaterialRegistry::builtin();
+    let stone = materials.create_tile(materials.find("Stone").unwrap(), 0);
+
+    let mut world = StreamingWorld::open(&directory, (4, 4), 3, materials.clone()).unwrap();
+
+    // one tile simulated from the start, another one far away from it
+    world.set_tile((10, 10).into(), stone).unwrap();
+    world.set_tile((-5000, 12000).into(), stone).unwrap();
+
+    world.set_center((100_000, -100_000).into()).unwrap();
+    assert!(!world.simulated_area().contains((10, 10).into()));
+    assert_eq!(world.get_tile((10, 10).into()).unwrap(), stone);
+
+    world.flush().unwrap();
+    drop(world);
+
+    let world = StreamingWorld::open(&directory, (4, 4), 3, materials).unwrap();
+    assert_eq!(world.get_tile((10, 10).into()).unwrap(), stone);
+    assert_eq!(world.get_tile((-5000, 12000).into()).unwrap(), stone);
+    assert_eq!(
+        world.get_tile((-5000, 12001).into()).unwrap().tile_type,
+        TileType::AIR
+    );
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn tiles_cross_the_edge_of_the_simulated_area() {
+    let directory = directory("edge");
+    let materials = MaterialRegistry::builtin();
+    let sand = materials.find("Sand").unwrap();
+
+    let mut world = StreamingWorld::open(&directory, (2, 2), 3, materials.clone()).unwrap();
+    let bottom = world.simulated_area().max.y;
+    world
+        .set_tile((0, bottom).into(), materials.create_tile(sand, 0))
+        .unwrap();
+
+    for _ in 0..20 {
+        world.step();
+    }
+
+    let column = |world: &StreamingWorld| -> Vec<i64> {
+        (bottom..bottom + 64)
+            .filter(|&y| world.get_tile((0, y).into()).unwrap().tile_type == sand)
+            .collect()
+    };
+
+    // the sand fell out of the simulated area and stayed just past its edge
+    let landed = column(&world);
+    assert_eq!(landed, [bottom + 1]);
+
+    world.set_center((50_000, 0).into()).unwrap();
+    assert_eq!(column(&world), landed);
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn least_recently_used_chunks_are_written_to_disk() {
+    let directory = directory("cache");
+    let materials = MaterialRegistry::builtin();
+    let stone = materials.create_tile(materials.find("Stone").unwrap(), 0);
+
+    let mut world = StreamingWorld::open(&directory, (2, 2), 3, materials).unwrap();
+    world.set_cache_capacity(2).unwrap();
+
+    // three chunks far from the loaded area, the first one being the least recently used
+    for x in [1000, 2000, 3000] {
+        world.set_tile((x, 0).into(), stone).unwrap();
+    }
+
+    assert!(directory.join("31_0.bin").exists());
+    assert!(!directory.join("62_0.bin").exists());
+    assert!(!directory.join("93_0.bin").exists());
+
+    for x in [1000, 2000, 3000] {
+        assert_eq!(world.get_tile((x, 0).into()).unwrap(), stone);
+    }
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn regions_are_copied_out_of_and_into_the_world() {
+    let directory = directory("region");
+    let materials = MaterialRegistry::builtin();
+    let water = materials.find("Water").unwrap();
+
+    let mut grid = Grid::with_materials((40, 3), 3, materials.clone());
+    for x in 0..40 {
+        grid.set_tile((x, 1).into(), materials.create_tile(water, 2));
+    }
+
+    let mut world = StreamingWorld::open(&directory, (2, 2), 3, materials).unwrap();
+    assert_eq!(world.content_bounds().unwrap(), None);
+
+    // straddling chunks both loaded and far away from the loaded area
+    let at = (-20, 5000).into();
+    world.paste(&grid, at).unwrap();
+    world.paste(&grid, (-20, 10).into()).unwrap();
+
+    let bounds = world.content_bounds().unwrap().unwrap();
+    assert_eq!(
+        bounds,
+        Rect {
+            min: (-20, 11).into(),
+            max: (19, 5001).into()
+        }
+    );
+
+    let copied = world
+        .region(Rect {
+            min: at,
+            max: at + (39, 2).into(),
+        })
+        .unwrap();
+    assert_eq!(copied.snapshot(), grid.snapshot());
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn simulated_areas_resize_around_their_anchor() {
+    let directory = directory("area");
+    let materials = MaterialRegistry::builtin();
+
+    let mut world = StreamingWorld::open(&directory, (2, 2), 3, materials).unwrap();
+    let area = world.simulated_area();
+
+    world.set_area((4, 3), ResizeAnchor::TopLeft).unwrap();
+    let grown = world.simulated_area();
+    assert_eq!(grown.min, area.min);
+    assert_eq!(
+        grown.max - grown.min,
+        (4 * CHUNK_SIZE - 1, 3 * CHUNK_SIZE - 1).into()
+    );
+
+    world.set_area((2, 1), ResizeAnchor::Center).unwrap();
+    let shrunk = world.simulated_area();
+    assert_eq!(shrunk.min, grown.min + (CHUNK_SIZE, CHUNK_SIZE).into());
+    assert_eq!(
+        shrunk.max - shrunk.min,
+        (2 * CHUNK_SIZE - 1, CHUNK_SIZE - 1).into()
+    );
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}